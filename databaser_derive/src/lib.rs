@@ -0,0 +1,235 @@
+// Derive macro mapping plain Rust structs onto databaser tables.
+//
+// #[derive(DbRecord)]
+// #[db(table = "Labratory_Equipment")]
+// struct EquipmentInfo {
+//     #[db(primary_key)]
+//     inventory_number: i64,
+//     #[db(rename = "department", sql_type = "TEXT")]
+//     unit: String,
+//     comment: Option<String>,
+// }
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, GenericArgument, LitStr, PathArguments, Type,
+};
+
+#[proc_macro_derive(DbRecord, attributes(db))]
+pub fn derive_db_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Column {
+    field: syn::Ident,
+    name: String,
+    sql_type: String,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+
+    // struct level attributes
+    let mut table_name = ident.to_string();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("db")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                table_name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unknown struct attribute, expected `table = \"...\"`"))
+            }
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => &named.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "DbRecord can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "DbRecord can only be derived for structs",
+            ))
+        }
+    };
+
+    // field level attributes
+    let mut columns: Vec<Column> = Vec::new();
+    let mut primary_key: Option<String> = None;
+    for field in fields {
+        let field_ident = field.ident.clone().expect("named field");
+        let mut name = field_ident.to_string();
+        let mut sql_type: Option<String> = None;
+        let mut is_pkey = false;
+
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("db")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("primary_key") {
+                    is_pkey = true;
+                    Ok(())
+                } else if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("sql_type") {
+                    sql_type = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "unknown field attribute, expected `primary_key`, `rename` or `sql_type`",
+                    ))
+                }
+            })?;
+        }
+
+        let mut sql_type = match sql_type.or_else(|| sql_type_of(&field.ty).map(String::from)) {
+            Some(t) => t,
+            None => {
+                return Err(syn::Error::new_spanned(
+                    &field.ty,
+                    "cannot infer SQL type, specify #[db(sql_type = \"...\")]",
+                ))
+            }
+        };
+
+        if is_pkey {
+            if primary_key.is_some() {
+                return Err(syn::Error::new_spanned(
+                    &field_ident,
+                    "only one field can be marked as #[db(primary_key)]",
+                ));
+            }
+            if !sql_type.contains("PRIMARY KEY") {
+                sql_type.push_str(" PRIMARY KEY");
+            }
+            primary_key = Some(name.clone());
+        }
+
+        columns.push(Column {
+            field: field_ident,
+            name,
+            sql_type,
+        });
+    }
+
+    let primary_key = primary_key.unwrap_or_default();
+    let col_names: Vec<&String> = columns.iter().map(|c| &c.name).collect();
+    let col_types: Vec<&String> = columns.iter().map(|c| &c.sql_type).collect();
+    let field_idents: Vec<&syn::Ident> = columns.iter().map(|c| &c.field).collect();
+
+    Ok(quote! {
+        impl ::databaser::DbRecord for #ident {
+            fn table() -> ::databaser::database::definitions::Table {
+                ::databaser::database::definitions::Table::new(
+                    #table_name,
+                    vec![#((#col_names.to_string(), #col_types.to_string())),*],
+                    #primary_key,
+                )
+            }
+
+            fn to_values(&self) -> Vec<::databaser::database::definitions::DataType> {
+                vec![#(::databaser::database::definitions::DataType::from(
+                    ::std::clone::Clone::clone(&self.#field_idents)
+                )),*]
+            }
+
+            fn from_row(row: &::databaser::rusqlite::Row) -> ::databaser::rusqlite::Result<Self> {
+                Ok(#ident {
+                    #(#field_idents: row.get(#col_names)?),*
+                })
+            }
+        }
+    })
+}
+
+// maps rust type of a field to SQL type used in table definition
+fn sql_type_of(ty: &Type) -> Option<&'static str> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+
+    match segment.ident.to_string().as_str() {
+        "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "isize" => Some("INTEGER"),
+        "f32" | "f64" => Some("FLOAT"),
+        "bool" => Some("BOOL"),
+        "String" => Some("TEXT"),
+        "Vec" => match inner_type(&segment.arguments)? {
+            Type::Path(inner) if inner.path.is_ident("u8") => Some("BLOB"),
+            _ => None,
+        },
+        // nullable column of the inner type
+        "Option" => sql_type_of(inner_type(&segment.arguments)?),
+        _ => None,
+    }
+}
+
+fn inner_type(args: &PathArguments) -> Option<&Type> {
+    match args {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn unknown_attributes() {
+        let input: DeriveInput = parse_quote! {
+            #[db(table = "T")]
+            struct Record {
+                #[db(primary)]
+                id: i64,
+            }
+        };
+        let err = expand(&input).unwrap_err();
+        assert!(
+            err.to_string().starts_with("unknown field attribute"),
+            "{}",
+            err
+        );
+
+        let input: DeriveInput = parse_quote! {
+            #[db(name = "T")]
+            struct Record {
+                id: i64,
+            }
+        };
+        let err = expand(&input).unwrap_err();
+        assert!(
+            err.to_string().starts_with("unknown struct attribute"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn table_built_by_constructor() {
+        let input: DeriveInput = parse_quote! {
+            struct Record {
+                #[db(primary_key)]
+                id: i64,
+            }
+        };
+        let tokens = expand(&input).unwrap().to_string();
+        assert!(tokens.contains("Table :: new"), "{}", tokens);
+    }
+}
@@ -1,59 +1,149 @@
+#[cfg(feature = "async")]
+pub mod async_api;
+pub mod audit;
+pub mod backup;
 pub mod configuration;
 pub mod definitions;
+pub mod dump;
 pub mod errors;
+pub mod lint;
+pub mod pool;
+pub mod record;
+pub mod registry;
+pub mod serialization;
 
 use super::query_builder as qr;
 use super::query_builder::ToQuery;
 
+use audit::{key_column, Operation, AUDIT_TABLE};
 use configuration::*;
 use definitions::*;
 use errors::*;
+use record::DbRecord;
 
-use csv::{ReaderBuilder, WriterBuilder};
-use rusqlite::{params, Connection, Params, Result};
-use std::error::Error;
-use std::fs;
-use std::fs::File;
-use std::path::Path;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, Params, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
 
 pub const STD_CONFIG_PATH: &str = "config.toml";
 
 impl DataBase {
+    // opens the first database of the config, `DataBaseRegistry` gives access to all of them
     pub fn from_config(config_path: &str) -> Result<Self, DataBaseError> {
-        // read config to string
-        let toml_content = fs::read_to_string(config_path).map_err(DataBaseError::from)?;
-        // parse it
-        let config: Config = toml::from_str(&toml_content).map_err(DataBaseError::from)?;
+        let database = load_databases(config_path, None)?.remove(0);
 
-        let connection: Connection = Connection::open(config.database.path.clone())?;
-        let tables: Vec<Table> = parse_tables_configs(&config)?;
+        Self::from_database_config(&database)
+    }
+
+    pub fn from_database_config(config: &DatabaseConfig) -> Result<Self, DataBaseError> {
+        config.validate()?;
+        let tables: Vec<Table> = parse_tables_configs(&config.tables, &config.views)
+            .context(|| format!("in tables of database \"{}\"", config.name()))?;
+
+        let mut db = Self::open(config, &config.connection, tables)?;
+        if config.audit {
+            db.enable_audit(&config.audit_user())?;
+        }
+
+        Ok(db)
+    }
+
+    // connection to the database of the config with tables parsed beforehand, changes are not
+    // recorded yet
+    pub(crate) fn open(
+        config: &DatabaseConfig,
+        options: &ConnectionOptions,
+        tables: Vec<Table>,
+    ) -> Result<Self, DataBaseError> {
+        let connection: Connection = open_connection(&config.path, options)
+            .context(|| format!("opening database \"{}\" at {}", config.name(), config.path))?;
+        connection.set_prepared_statement_cache_capacity(config.statement_cache);
 
-        Ok(DataBase { connection, tables })
+        Ok(DataBase {
+            connection,
+            tables,
+            audit: None,
+        })
     }
 
     pub fn from_file(db_path: &str) -> Result<Self, DataBaseError> {
-        let connection = Connection::open(db_path)?;
+        Self::from_file_with(db_path, &ConnectionOptions::default())
+    }
+
+    pub fn from_file_with(
+        db_path: &str,
+        options: &ConnectionOptions,
+    ) -> Result<Self, DataBaseError> {
+        let connection = open_connection(db_path, options)
+            .context(|| format!("opening database at {}", db_path))?;
         let tables: Vec<Table> = get_tables_from_file(&connection)?;
 
-        Ok(DataBase { connection, tables })
+        // keep recording changes of databases that have been audited, read-only ones do not change
+        let audited: bool = connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = ?);",
+            [AUDIT_TABLE],
+            |row| row.get(0),
+        )?;
+        let mut db = DataBase {
+            connection,
+            tables,
+            audit: None,
+        };
+        if audited && !options.read_only {
+            db.enable_audit(&audit::default_user())?;
+        }
+
+        Ok(db)
     }
 
     pub fn show_structure(&self) {
         println!("Database contains {} tables:", self.tables.len());
         for (i, tbl) in self.tables.iter().enumerate() {
-            println!("\t{} TABLE {}", i, tbl.name);
+            let kind = if tbl.view.is_some() { "VIEW" } else { "TABLE" };
+            println!(
+                "\t{} {} {}{}",
+                i,
+                kind,
+                tbl.name,
+                if tbl.soft_delete {
+                    " (soft delete)"
+                } else {
+                    ""
+                }
+            );
             for col in &tbl.columns {
-                println!("\t\t{} {}", col.0, col.1);
+                match tbl.column_docs.iter().find(|doc| doc.column == col.0) {
+                    Some(ColumnDoc {
+                        description: Some(description),
+                        ..
+                    }) => println!("\t\t{} {} -- {}", col.0, col.1, description),
+                    _ => println!("\t\t{} {}", col.0, col.1),
+                }
+            }
+            for index in &tbl.indexes {
+                println!(
+                    "\t\t{}INDEX {} ({})",
+                    if index.unique { "UNIQUE " } else { "" },
+                    index.name,
+                    index.columns.join(", ")
+                );
             }
         }
     }
 
-    pub fn table_shape(self: &Self, table_name: &str) -> Result<(usize, usize), DataBaseError> {
+    pub fn table_shape(&self, table_name: &str) -> Result<(usize, usize), DataBaseError> {
         if let Some(table_idx) = self.tables.iter().position(|x| x.name == table_name) {
+            let mut q = qr::Count::new(table_name);
+            if self.tables[table_idx].soft_delete {
+                q = q.condition(&format!("{} IS NULL", qr::quote_ident(DELETED_AT)));
+            }
+            let q = q.to_query();
             let count = self
                 .connection
-                .query_row(&qr::Count::new(table_name).to_query(), [], |row| row.get(0))
-                .map_err(DataBaseError::from)?;
+                .prepare_cached(&q)
+                .and_then(|mut stmt| stmt.query_row([], |row| row.get(0)))
+                .context(|| format!("in query {}", q))?;
             return Ok((self.tables[table_idx].columns.len(), count));
         }
         Err(DataBaseError::TableNotFound(format!(
@@ -62,55 +152,748 @@ impl DataBase {
         )))
     }
 
-    pub fn create_table(
-        self: &Self,
-        table_name: &str,
-        columns: &Vec<(String, String)>,
-    ) -> Result<(), DataBaseError> {
+    pub fn create_table(&self, table: &Table) -> Result<(), DataBaseError> {
+        self.connection
+            .execute(create_table_query(table).as_str(), params![])
+            .context(|| format!("creating table \"{}\"", table.name))?;
+
+        Ok(())
+    }
+
+    pub fn create_full_text(&self, table: &Table) -> Result<(), DataBaseError> {
+        let q = qr::CreateFullText::new(&table.name).columns(&table.full_text);
+        let exists: bool = self.connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = ?);",
+            [q.fts_table_name()],
+            |row| row.get(0),
+        )?;
+
+        self.connection
+            .execute_batch(&q.to_query())
+            .context(|| format!("creating full-text index of \"{}\"", table.name))?;
+        // rows inserted before the index existed are not known to it yet
+        if !exists {
+            self.connection.execute(
+                &format!(
+                    "INSERT INTO {0} ({0}) VALUES ('rebuild');",
+                    qr::quote_ident(&q.fts_table_name())
+                ),
+                params![],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn create_view(&self, view_name: &str, select: &str) -> Result<(), DataBaseError> {
         self.connection
             .execute(
-                qr::CreateTable::new(table_name)
-                    .columns(&get_col_names(columns))
+                qr::CreateView::new(view_name)
+                    .select(select)
                     .to_query()
                     .as_str(),
                 params![],
             )
-            .map_err(DataBaseError::from)?;
+            .context(|| format!("creating view \"{}\"", view_name))?;
 
         Ok(())
     }
 
     pub fn insert(
-        self: &Self,
+        &self,
         table_name: &str,
         vals: Option<&Vec<DataType>>,
     ) -> Result<(), DataBaseError> {
         if let Some(table_idx) = self.tables.iter().position(|x| x.name == table_name) {
-            let q = qr::Insert::new(table_name)
-                .columns(&get_col_names(&self.tables[table_idx].columns))
-                .values(vals.cloned())
-                .to_query();
+            let table = &self.tables[table_idx];
+            let columns = get_col_names(&table.columns);
+            let vals: Vec<DataType> = match vals {
+                Some(vals) => vals.clone(),
+                None => vec![DataType::Null; columns.len()],
+            };
+            let key = inserted_key(table, &columns, &vals);
+            // values are bound to a cached statement, placeholders go into the query as they are
+            let (q, params) = if vals.iter().any(|v| matches!(v, DataType::Param(_))) {
+                let q = qr::Insert::new(table_name)
+                    .columns(&columns)
+                    .values(Some(vals));
+                (q.to_query(), Vec::new())
+            } else {
+                let q = qr::Insert::new(table_name).columns(&columns).values(None);
+                (q.to_query(), vals)
+            };
             println!("{}", q);
-            self.connection
-                .execute(q.as_str(), params![])
-                .map_err(DataBaseError::from)?;
+            self.audited(table, Operation::Insert, None, key.as_ref(), || {
+                self.execute_cached(&q, params_from_iter(params))
+                    .map_err(|e| constraint_error(table_name, Operation::Insert, e))
+            })?;
 
-            return Ok(());
+            Ok(())
         } else {
-            return Err(DataBaseError::TableNotFound(format!(
+            Err(DataBaseError::TableNotFound(format!(
                 "No table \"{}\" found in database ",
                 table_name
+            )))
+        }
+    }
+
+    pub fn insert_record<T: DbRecord>(&self, record: &T) -> Result<(), DataBaseError> {
+        let record_table = T::table();
+        let table = self.table(&record_table.name)?;
+
+        let columns = get_col_names(&record_table.columns);
+        let vals = record.to_values();
+        let q = qr::Insert::new(&table.name)
+            .columns(&columns)
+            .values(None)
+            .to_query();
+        let key = inserted_key(table, &columns, &vals);
+        self.audited(table, Operation::Insert, None, key.as_ref(), || {
+            self.execute_cached(&q, params_from_iter(vals))
+                .map_err(|e| constraint_error(&table.name, Operation::Insert, e))
+        })?;
+
+        Ok(())
+    }
+
+    pub fn select(&self, select: &qr::Select) -> Result<Vec<Vec<DataType>>, DataBaseError> {
+        self.validate_select(select)?;
+
+        let q = self.live(select).to_query();
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .context(|| format!("in query {}", q))?;
+        let column_count = stmt.column_count();
+        let rows = stmt
+            .query_map([], |row| {
+                let mut row_data = Vec::new();
+                for col_idx in 0..column_count {
+                    row_data.push(row.get::<usize, DataType>(col_idx)?);
+                }
+                Ok(row_data)
+            })?
+            .collect::<Result<Vec<Vec<DataType>>>>()
+            .map_err(DataBaseError::from)?;
+
+        Ok(rows)
+    }
+
+    // rows matching FTS5 query (`word`, `"exact phrase"`, `pref*`, `a OR b`...), best first
+    pub fn search(
+        &self,
+        table_name: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, DataBaseError> {
+        let table = self.table(table_name)?;
+        if table.full_text.is_empty() {
+            return Err(DataBaseError::ConfigError(format!(
+                "Full-text search is not enabled for table \"{}\"",
+                table_name
             )));
         }
+
+        let fts = qr::quote_ident(&qr::full_text_table(table_name));
+        let mut columns: Vec<String> = table
+            .columns
+            .iter()
+            .map(|(name, _)| format!("t.{}", qr::quote_ident(name)))
+            .collect();
+        for idx in 0..table.full_text.len() {
+            columns.push(format!("highlight({}, {}, '[', ']')", fts, idx));
+        }
+        columns.push(format!("{}.rank", fts));
+
+        let live = if table.soft_delete {
+            format!(" AND t.{} IS NULL", qr::quote_ident(DELETED_AT))
+        } else {
+            String::new()
+        };
+        let q = format!(
+            "SELECT {} FROM {} JOIN {} AS t ON t.rowid = {}.rowid WHERE {} MATCH ?{} ORDER BY rank LIMIT ?;",
+            columns.join(", "),
+            fts,
+            qr::quote_ident(table_name),
+            fts,
+            fts,
+            live
+        );
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .context(|| format!("in query {}", q))?;
+        let n_cols = table.columns.len();
+        let hits = stmt
+            .query_map(params![query, limit as i64], |row| {
+                let mut hit = SearchHit {
+                    row: Vec::new(),
+                    highlights: Vec::new(),
+                    rank: row.get(n_cols + table.full_text.len())?,
+                };
+                for col_idx in 0..n_cols {
+                    hit.row.push(row.get::<usize, DataType>(col_idx)?);
+                }
+                for (idx, col) in table.full_text.iter().enumerate() {
+                    let text: Option<String> = row.get(n_cols + idx)?;
+                    hit.highlights.push((col.clone(), text.unwrap_or_default()));
+                }
+                Ok(hit)
+            })?
+            .collect::<Result<Vec<SearchHit>>>()
+            .context(|| format!("searching \"{}\" for {}", table_name, query))?;
+
+        Ok(hits)
     }
 
-    pub fn construct_tables(self: &Self) -> Result<(), DataBaseError> {
-        for table in &self.tables {
-            self.create_table(&table.name, &table.columns)?;
+    // keyset pagination: rows ordered by primary key (rowid if there is none) and starting after
+    // the given key, so walking a large table does not rescan the skipped rows as OFFSET does
+    pub fn page(
+        &self,
+        table_name: &str,
+        after: Option<&DataType>,
+        page_size: usize,
+    ) -> Result<Page, DataBaseError> {
+        self.page_filtered(table_name, &[], after, page_size)
+    }
+
+    // page of rows having given values in given columns, NULL matches empty cells
+    pub fn page_filtered(
+        &self,
+        table_name: &str,
+        filters: &[(String, DataType)],
+        after: Option<&DataType>,
+        page_size: usize,
+    ) -> Result<Page, DataBaseError> {
+        let table = self.table(table_name)?;
+        if let Some((col, _)) = filters
+            .iter()
+            .find(|(c, _)| !table.columns.iter().any(|(name, _)| name == c))
+        {
+            return Err(DataBaseError::ColumnNotFound(format!(
+                "No column \"{}\" in table \"{}\"",
+                col, table_name
+            )));
+        }
+
+        if table.view.is_some() && table.primary_key.is_empty() {
+            return Err(DataBaseError::ColumnNotFound(format!(
+                "View \"{}\" has no primary key to walk it by",
+                table_name
+            )));
+        }
+
+        // key is selected as an extra first column and cut off from the results
+        let key = key_column(table);
+        let mut columns = vec![key.to_string()];
+        columns.extend(get_col_names(&table.columns));
+
+        let mut conditions: Vec<String> = Vec::new();
+        let mut params: Vec<&DataType> = Vec::new();
+        for (col, val) in filters {
+            if *val == DataType::Null {
+                conditions.push(format!("{} IS NULL", qr::quote_ident(col)));
+            } else {
+                conditions.push(format!("{} = ?", qr::quote_ident(col)));
+                params.push(val);
+            }
+        }
+        if let Some(key_val) = after {
+            conditions.push(format!("{} > ?", qr::quote_ident(key)));
+            params.push(key_val);
+        }
+
+        let mut q = qr::Select::new(table_name)
+            .columns(&columns)
+            .order_by(key, qr::Order::Asc)
+            .limit(page_size + 1); // one more row tells if there is a next page
+        if !conditions.is_empty() {
+            q = q.conditions(&conditions.join(" AND "));
+        }
+
+        let q = self.live(&q).to_query();
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .context(|| format!("in query {}", q))?;
+        let mut rows = stmt.query(params_from_iter(params))?;
+
+        let mut page = Page {
+            rows: Vec::new(),
+            next: None,
+        };
+        let mut keys: Vec<DataType> = Vec::new();
+        while let Some(row) = rows.next()? {
+            keys.push(row.get::<usize, DataType>(0)?);
+            let mut row_data = Vec::new();
+            for col_idx in 1..columns.len() {
+                row_data.push(row.get::<usize, DataType>(col_idx)?);
+            }
+            page.rows.push(row_data);
+        }
+        if page.rows.len() > page_size {
+            page.rows.truncate(page_size);
+            page.next = page_size.checked_sub(1).map(|last| keys.swap_remove(last));
+        }
+
+        Ok(page)
+    }
+
+    // checks that tables, aliases and columns used by the query are known
+    pub fn validate_select(&self, select: &qr::Select) -> Result<(), DataBaseError> {
+        let mut sources: Vec<(&str, &Table)> = Vec::new();
+        for (table_name, alias) in select.sources() {
+            let table = match self.tables.iter().find(|x| x.name == table_name) {
+                Some(t) => t,
+                None => {
+                    return Err(DataBaseError::TableNotFound(format!(
+                        "No table \"{}\" found in database ",
+                        table_name
+                    )))
+                }
+            };
+            let name = alias.unwrap_or(table_name);
+            if sources.iter().any(|(n, _)| *n == name) {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Table name or alias \"{}\" is used more than once in query",
+                    name
+                )));
+            }
+            sources.push((name, table));
+        }
+
+        let has_column = |table: &Table, col: &str| {
+            table.columns.iter().any(|(c, _)| c == col) || (table.soft_delete && col == DELETED_AT)
+        };
+        for col in select.selected_columns().into_iter().flatten() {
+            if col == "*" {
+                continue;
+            }
+
+            match col.split_once('.') {
+                None => {
+                    let name = col.as_str();
+                    let matches = sources.iter().filter(|(_, t)| has_column(t, name)).count();
+                    if matches == 0 {
+                        return Err(DataBaseError::ColumnNotFound(format!(
+                            "No column \"{}\" in tables of query",
+                            name
+                        )));
+                    } else if matches > 1 {
+                        return Err(DataBaseError::ColumnNotFound(format!(
+                            "Column \"{}\" is ambiguous, qualify it with table name or alias",
+                            name
+                        )));
+                    }
+                }
+                Some((source, name)) => match sources.iter().find(|(n, _)| *n == source) {
+                    Some(_) if name == "*" => {}
+                    Some((_, table)) if has_column(table, name) => {}
+                    Some((_, table)) => {
+                        return Err(DataBaseError::ColumnNotFound(format!(
+                            "No column \"{}\" in table \"{}\"",
+                            name, table.name
+                        )))
+                    }
+                    None => {
+                        return Err(DataBaseError::TableNotFound(format!(
+                            "Table or alias \"{}\" is not part of query",
+                            source
+                        )))
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    // select skipping soft deleted rows of its tables, unless it asks to keep them
+    fn live(&self, select: &qr::Select) -> qr::Select {
+        let mut live = select.clone();
+        if select.includes_deleted() {
+            return live;
+        }
+        for (table_name, _) in select.sources() {
+            if self
+                .tables
+                .iter()
+                .any(|t| t.name == table_name && t.soft_delete)
+            {
+                live = live.hide_deleted(table_name);
+            }
+        }
+
+        live
+    }
+
+    pub fn select_records<T: DbRecord>(
+        &self,
+        select: &qr::Select,
+    ) -> Result<Vec<T>, DataBaseError> {
+        self.validate_select(select)?;
+
+        let q = self.live(select).to_query();
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .context(|| format!("in query {}", q))?;
+        let records = stmt
+            .query_map([], |row| T::from_row(row))?
+            .collect::<Result<Vec<T>>>()
+            .map_err(DataBaseError::from)?;
+
+        Ok(records)
+    }
+
+    pub fn select_as<T: DeserializeOwned>(
+        &self,
+        select: &qr::Select,
+    ) -> Result<Vec<T>, DataBaseError> {
+        self.validate_select(select)?;
+
+        let q = self.live(select).to_query();
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .context(|| format!("in query {}", q))?;
+        let mut rows = stmt.query([])?;
+
+        let mut result: Vec<T> = Vec::new();
+        while let Some(row) = rows.next()? {
+            result.push(serialization::row_to_struct(row)?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn insert_from<T: Serialize>(
+        &self,
+        table_name: &str,
+        record: &T,
+    ) -> Result<(), DataBaseError> {
+        if let Some(table_idx) = self.tables.iter().position(|x| x.name == table_name) {
+            let (columns, vals) = serialization::struct_to_values(record)?;
+            let known_columns = get_col_names(&self.tables[table_idx].columns);
+            if let Some(col) = columns.iter().find(|c| !known_columns.contains(c)) {
+                return Err(DataBaseError::SerdeError(format!(
+                    "Table \"{}\" has no column \"{}\"",
+                    table_name, col
+                )));
+            }
+
+            let values: Vec<(String, DataType)> = columns.into_iter().zip(vals).collect();
+            self.insert_values(table_name, &values)?;
+
+            Ok(())
+        } else {
+            Err(DataBaseError::TableNotFound(format!(
+                "No table \"{}\" found in database ",
+                table_name
+            )))
+        }
+    }
+
+    // inserts a row with given columns, the rest get their default values. Returns primary key
+    // of the row, rowid if it is assigned by sqlite
+    pub fn insert_values(
+        &self,
+        table_name: &str,
+        values: &[(String, DataType)],
+    ) -> Result<DataType, DataBaseError> {
+        let table = self.writable_table(table_name)?;
+        if let Some((col, _)) = values
+            .iter()
+            .find(|(c, _)| !table.columns.iter().any(|(name, _)| name == c))
+        {
+            return Err(DataBaseError::ColumnNotFound(format!(
+                "No column \"{}\" in table \"{}\"",
+                col, table_name
+            )));
+        }
+
+        let columns: Vec<String> = values.iter().map(|(col, _)| col.clone()).collect();
+        let vals: Vec<DataType> = values.iter().map(|(_, val)| val.clone()).collect();
+        let q = qr::Insert::new(table_name)
+            .columns(&columns)
+            .values(None)
+            .to_query();
+        let key = inserted_key(table, &columns, &vals);
+        // taken before the change is recorded, the audit log has rowids of its own
+        let mut rowid = 0;
+        self.audited(table, Operation::Insert, None, key.as_ref(), || {
+            let changed = self
+                .execute_cached(&q, params_from_iter(vals))
+                .map_err(|e| constraint_error(table_name, Operation::Insert, e))?;
+            rowid = self.connection.last_insert_rowid();
+            Ok(changed)
+        })?;
+
+        Ok(key.unwrap_or(DataType::Int(rowid)))
+    }
+
+    // sets given columns of the row with primary key `key`, returns false if there is no such row
+    pub fn update(
+        &self,
+        table_name: &str,
+        key: &DataType,
+        values: &[(String, DataType)],
+    ) -> Result<bool, DataBaseError> {
+        let table = self.writable_table(table_name)?;
+        if let Some((col, _)) = values
+            .iter()
+            .find(|(c, _)| !table.columns.iter().any(|(name, _)| name == c))
+        {
+            return Err(DataBaseError::ColumnNotFound(format!(
+                "No column \"{}\" in table \"{}\"",
+                col, table_name
+            )));
+        }
+
+        let columns: Vec<String> = values.iter().map(|(col, _)| col.clone()).collect();
+        let mut condition = format!("{} = ?", qr::quote_ident(key_column(table)));
+        // soft deleted rows are left as they are
+        if table.soft_delete {
+            condition.push_str(&format!(" AND {} IS NULL", qr::quote_ident(DELETED_AT)));
+        }
+        let q = qr::Update::new(table_name)
+            .columns(&columns)
+            .condition(&condition)
+            .to_query();
+        let mut params: Vec<&DataType> = values.iter().map(|(_, val)| val).collect();
+        params.push(key);
+
+        // primary key itself may be changed
+        let new_key = values
+            .iter()
+            .find(|(col, _)| *col == table.primary_key)
+            .map_or(key, |(_, val)| val);
+        let changed = self.audited(table, Operation::Update, Some(key), Some(new_key), || {
+            self.execute_cached(&q, params_from_iter(params))
+                .map_err(|e| constraint_error(table_name, Operation::Update, e))
+        })?;
+
+        Ok(changed > 0)
+    }
+
+    // deletes the row with primary key `key`, returns false if there is no such row.
+    // Rows of tables with soft delete are only marked as deleted
+    pub fn delete(&self, table_name: &str, key: &DataType) -> Result<bool, DataBaseError> {
+        let table = self.writable_table(table_name)?;
+        if !table.soft_delete {
+            return self.remove(table, key, Operation::Delete);
+        }
+
+        let q = qr::Update::new(table_name)
+            .columns(&[DELETED_AT.to_string()])
+            .condition(&format!(
+                "{} = ? AND {} IS NULL",
+                qr::quote_ident(key_column(table)),
+                qr::quote_ident(DELETED_AT)
+            ))
+            .to_query();
+        let now: String = self.connection.query_row(
+            "SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now');",
+            [],
+            |row| row.get(0),
+        )?;
+        let changed = self.audited(table, Operation::Delete, Some(key), None, || {
+            self.execute_cached(&q, params![now, key])
+                .map_err(DataBaseError::from)
+        })?;
+
+        Ok(changed > 0)
+    }
+
+    // brings back soft deleted row, returns false if there is no such deleted row
+    pub fn restore(&self, table_name: &str, key: &DataType) -> Result<bool, DataBaseError> {
+        let table = self.soft_table(table_name)?;
+
+        let q = qr::Update::new(table_name)
+            .columns(&[DELETED_AT.to_string()])
+            .condition(&format!(
+                "{} = ? AND {} IS NOT NULL",
+                qr::quote_ident(key_column(table)),
+                qr::quote_ident(DELETED_AT)
+            ))
+            .to_query();
+        let changed = self.audited(table, Operation::Restore, None, Some(key), || {
+            self.execute_cached(&q, params![DataType::Null, key])
+                .map_err(DataBaseError::from)
+        })?;
+
+        Ok(changed > 0)
+    }
+
+    // removes soft deleted rows of the table for good, returns their number
+    pub fn purge(&self, table_name: &str) -> Result<usize, DataBaseError> {
+        let table = self.soft_table(table_name)?;
+
+        let q = qr::Select::new(table_name)
+            .columns(&vec![key_column(table).to_string()])
+            .conditions(&format!("{} IS NOT NULL", qr::quote_ident(DELETED_AT)))
+            .to_query();
+        let mut stmt = self.connection.prepare(&q).map_err(DataBaseError::from)?;
+        let keys = stmt
+            .query_map([], |row| row.get::<usize, DataType>(0))?
+            .collect::<Result<Vec<DataType>>>()
+            .map_err(DataBaseError::from)?;
+
+        let mut purged = 0;
+        for key in &keys {
+            if self.remove(table, key, Operation::Purge)? {
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+
+    fn remove(
+        &self,
+        table: &Table,
+        key: &DataType,
+        operation: Operation,
+    ) -> Result<bool, DataBaseError> {
+        let q = qr::Delete::new(&table.name)
+            .condition(&format!("{} = ?", qr::quote_ident(key_column(table))))
+            .to_query();
+        let changed = self.audited(table, operation, Some(key), None, || {
+            self.execute_cached(&q, [key])
+                .map_err(|e| constraint_error(&table.name, operation, e))
+        })?;
+
+        Ok(changed > 0)
+    }
+
+    // row with primary key `key` (rowid if there is none), None if there is no such live row
+    pub fn get(
+        &self,
+        table_name: &str,
+        key: &DataType,
+    ) -> Result<Option<Vec<DataType>>, DataBaseError> {
+        let table = self.table(table_name)?;
+        let q = qr::Select::new(table_name)
+            .columns(&get_col_names(&table.columns))
+            .conditions(&format!("{} = ?", qr::quote_ident(key_column(table))));
+        let q = self.live(&q).to_query();
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .context(|| format!("in query {}", q))?;
+        let mut rows = stmt.query([key])?;
+
+        match rows.next()? {
+            Some(row) => {
+                let mut row_data = Vec::new();
+                for col_idx in 0..table.columns.len() {
+                    row_data.push(row.get::<usize, DataType>(col_idx)?);
+                }
+                Ok(Some(row_data))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // runs `f` in a transaction, committed if `f` succeeds and rolled back otherwise
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&DataBase) -> Result<T, DataBaseError>,
+    ) -> Result<T, DataBaseError> {
+        // the write lock is taken at once, so concurrent transactions do not deadlock
+        self.connection.execute_batch("BEGIN IMMEDIATE;")?;
+        match f(self) {
+            Ok(val) => {
+                self.connection.execute_batch("COMMIT;")?;
+                Ok(val)
+            }
+            Err(err) => {
+                if !self.connection.is_autocommit() {
+                    self.connection.execute_batch("ROLLBACK;")?;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    // statements of repeated changes are prepared once and kept in the connection's cache
+    fn execute_cached<P: Params>(&self, q: &str, params: P) -> Result<usize> {
+        self.connection.prepare_cached(q)?.execute(params)
+    }
+
+    fn table(&self, table_name: &str) -> Result<&Table, DataBaseError> {
+        self.tables
+            .iter()
+            .find(|x| x.name == table_name)
+            .ok_or_else(|| {
+                DataBaseError::TableNotFound(format!(
+                    "No table \"{}\" found in database ",
+                    table_name
+                ))
+            })
+    }
+
+    fn writable_table(&self, table_name: &str) -> Result<&Table, DataBaseError> {
+        let table = self.table(table_name)?;
+        if table.view.is_some() {
+            return Err(DataBaseError::ConfigError(format!(
+                "\"{}\" is a view, its rows cannot be changed",
+                table_name
+            )));
+        }
+
+        Ok(table)
+    }
+
+    fn soft_table(&self, table_name: &str) -> Result<&Table, DataBaseError> {
+        let table = self.writable_table(table_name)?;
+        if !table.soft_delete {
+            return Err(DataBaseError::ConfigError(format!(
+                "Soft delete is not enabled for table \"{}\"",
+                table_name
+            )));
+        }
+
+        Ok(table)
+    }
+
+    pub fn create_index(&self, table_name: &str, index: &Index) -> Result<(), DataBaseError> {
+        let mut q = qr::CreateIndex::new(&index.name, table_name).columns(&index.columns);
+        if index.unique {
+            q = q.unique();
+        }
+        if let Some(cond) = &index.condition {
+            q = q.condition(cond);
+        }
+
+        self.connection
+            .execute(q.to_query().as_str(), params![])
+            .context(|| format!("creating index \"{}\" of \"{}\"", index.name, table_name))?;
+
+        Ok(())
+    }
+
+    // referenced tables are created before the tables referring to them
+    pub fn construct_tables(&self) -> Result<(), DataBaseError> {
+        let (views, tables): (Vec<Table>, Vec<Table>) =
+            self.tables.iter().cloned().partition(|t| t.view.is_some());
+
+        for table in order_by_dependencies(&tables)? {
+            self.create_table(table)?;
+            for index in &table.indexes {
+                self.create_index(&table.name, index)?;
+            }
+            if !table.full_text.is_empty() {
+                self.create_full_text(table)?;
+            }
+        }
+        // views go last, in order of definition, as they can select from tables and other views
+        for view in &views {
+            self.create_view(&view.name, view.view.as_deref().unwrap_or_default())?;
         }
         Ok(())
     }
-    
+
     /*
     pub fn create_from_csv(self: &mut Self, path: &str) -> Result<(), DataBaseError> {
         let file = File::open(path).map_err(|e| DataBaseError::IoError(e))?;
@@ -154,7 +937,7 @@ impl DataBase {
         }
 
         // Get the data from the table using the select method
-        let query = format!("SELECT * FROM {}", table_name);
+        let query = qr::Select::new(table_name).to_query();
         let mut stmt = self
             .connection
             .prepare(&query)
@@ -201,41 +984,33 @@ impl DataBase {
         // Iterate over all tables in the database
         for table in &self.tables {
             println!("Table: {}", table.name);
-    
+
             // Execute a SELECT query to get all rows from the current table
-            let query = format!("SELECT * FROM {}", table.name);
+            let select = qr::Select::new(&table.name).columns(&get_col_names(&table.columns));
+            let query = self.live(&select).to_query();
             let mut stmt = self
                 .connection
                 .prepare(&query)
-                .map_err(DataBaseError::from)?;
-            let column_names = stmt.column_names().to_vec(); // Get column names for display
-    
+                .context(|| format!("in query {}", query))?;
+            let column_names: Vec<&str> = table
+                .columns
+                .iter()
+                .map(|(name, _)| table.label(name))
+                .collect(); // Get column labels for display
+
             let rows = stmt
                 .query_map([], |row| {
                     let mut row_data = Vec::new();
-                    for (col_idx, _) in &table.columns {
-                        let value = row.get::<usize, rusqlite::types::Value>(*col_idx); // Use rusqlite's Value type
-                        match value {
-                            Ok(val) => {
-                                // Convert the value to a human-readable string representation
-                                row_data.push(match val {
-                                    rusqlite::types::Value::Null => "NULL".to_string(),
-                                    rusqlite::types::Value::Integer(i) => i.to_string(),
-                                    rusqlite::types::Value::Real(f) => f.to_string(),
-                                    rusqlite::types::Value::Text(s) => s,
-                                    rusqlite::types::Value::Blob(_) => "[BLOB]".to_string(),
-                                });
-                            }
-                            Err(e) => return Err(e), // Convert rusqlite::Error into DataBaseError
-                        }
+                    for col_idx in 0..table.columns.len() {
+                        row_data.push(row.get::<usize, DataType>(col_idx)?.to_string());
                     }
                     Ok(row_data)
                 })
                 .map_err(DataBaseError::from)?;
-    
+
             // Print the column names
             println!("{:?}", column_names);
-    
+
             // Iterate through the rows and print them
             for row_result in rows {
                 match row_result {
@@ -251,28 +1026,235 @@ impl DataBase {
         }
         Ok(())
     }
-    
 }
 
 //////// AUXILLARY FUNCTIONS ////////
-fn parse_tables_configs(config: &Config) -> Result<Vec<Table>, DataBaseError> {
+// opens the file, or a database in memory for `:memory:`, and applies the options
+fn open_connection(path: &str, options: &ConnectionOptions) -> Result<Connection, DataBaseError> {
+    let mut flags = OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    if options.read_only {
+        flags |= OpenFlags::SQLITE_OPEN_READ_ONLY;
+    } else {
+        flags |= OpenFlags::SQLITE_OPEN_READ_WRITE;
+        if options.create {
+            flags |= OpenFlags::SQLITE_OPEN_CREATE;
+        }
+    }
+    let connection = if path == MEMORY_PATH {
+        Connection::open_in_memory_with_flags(flags)?
+    } else {
+        Connection::open_with_flags(path, flags)?
+    };
+
+    // set first, changing the journal mode waits for locks as well
+    if let Some(timeout) = options.busy_timeout {
+        connection.busy_timeout(Duration::from_millis(timeout))?;
+    }
+    if let Some(mode) = options.journal_mode {
+        let actual: String =
+            connection
+                .pragma_update_and_check(None, "journal_mode", mode.as_str(), |row| row.get(0))?;
+        // e.g. databases in memory have no WAL
+        if !actual.eq_ignore_ascii_case(mode.as_str()) {
+            return Err(DataBaseError::ConfigError(format!(
+                "Journal mode \"{}\" is not available, the database uses \"{}\"",
+                mode.as_str(),
+                actual
+            )));
+        }
+    }
+    if let Some(level) = options.synchronous {
+        connection.pragma_update(None, "synchronous", level.as_str())?;
+    }
+    if let Some(size) = options.cache_size {
+        connection.pragma_update(None, "cache_size", size)?;
+    }
+    let foreign_keys = if options.foreign_keys { "ON" } else { "OFF" };
+    connection.pragma_update(None, "foreign_keys", foreign_keys)?;
+
+    Ok(connection)
+}
+
+fn parse_tables_configs(
+    tables_configs: &[TableConfig],
+    views_configs: &[ViewConfig],
+) -> Result<Vec<Table>, DataBaseError> {
     let mut tables: Vec<Table> = Vec::new();
 
-    for table_config in &config.tables {
-        let (columns, pkey) = parse_columns(&table_config).map_err(DataBaseError::from)?;
+    for table_config in tables_configs {
+        validate_identifier("table", &table_config.name)?;
+        let (columns, pkey) = parse_columns(table_config)?;
+        let indexes = parse_indexes(table_config, &columns)?;
+        let col_names = get_col_names(&columns);
+        if let Some((_, err)) = unknown_columns(
+            &table_config.name,
+            "Full-text index",
+            &table_config.full_text,
+            &col_names,
+        )
+        .into_iter()
+        .next()
+        {
+            return Err(err);
+        }
         tables.push(Table {
             name: table_config.name.clone(),
             primary_key: pkey,
             columns,
+            indexes,
+            foreign_keys: Vec::new(),
+            view: None,
+            full_text: table_config.full_text.clone(),
+            soft_delete: table_config.soft_delete,
+            column_docs: parse_column_docs(table_config),
         });
     }
 
+    // references can be resolved only when all tables are known
+    for (idx, table_config) in tables_configs.iter().enumerate() {
+        tables[idx].foreign_keys = parse_foreign_keys(table_config, &tables)?;
+    }
+
+    if !views_configs.is_empty() {
+        let views = parse_views(views_configs, &tables)?;
+        tables.extend(views);
+    }
+
     Ok(tables)
 }
 
+// Columns of a view are known only to sqlite, so the configured tables and views are created
+// in an in-memory database and the columns are read back. This also checks the view queries.
+fn parse_views(
+    views_configs: &[ViewConfig],
+    tables: &[Table],
+) -> Result<Vec<Table>, DataBaseError> {
+    let scratch = Connection::open_in_memory()?;
+    for table in tables {
+        scratch.execute(create_table_query(table).as_str(), params![])?;
+    }
+
+    let mut views: Vec<Table> = Vec::new();
+    for view_config in views_configs {
+        validate_identifier("view", &view_config.name)?;
+        if tables
+            .iter()
+            .chain(views.iter())
+            .any(|t| t.name == view_config.name)
+        {
+            return Err(DataBaseError::ConfigError(format!(
+                "View \"{}\" has the same name as another table or view",
+                view_config.name
+            )));
+        }
+
+        let select = match (&view_config.sql, &view_config.table) {
+            (Some(sql), None) => sql.trim().trim_end_matches(';').to_string(),
+            (None, Some(table)) => {
+                let mut q = qr::Select::new(table);
+                if let Some(alias) = &view_config.alias {
+                    q = q.alias(alias);
+                }
+                if !view_config.columns.is_empty() {
+                    q = q.columns(&view_config.columns);
+                }
+                for join in &view_config.joins {
+                    q = q.join(join.kind, &join.table, join.alias.as_deref(), &join.on);
+                }
+                if let Some(cond) = &view_config.condition {
+                    q = q.conditions(cond);
+                }
+                // like selects, views see only live rows
+                for table in tables.iter().filter(|t| t.soft_delete) {
+                    q = q.hide_deleted(&table.name);
+                }
+                q.to_query().trim_end_matches(';').to_string()
+            }
+            _ => {
+                return Err(DataBaseError::ConfigError(format!(
+                    "View \"{}\" must be defined either with \"sql\" or with \"table\"",
+                    view_config.name
+                )))
+            }
+        };
+
+        scratch
+            .execute(
+                qr::CreateView::new(&view_config.name)
+                    .select(&select)
+                    .to_query()
+                    .as_str(),
+                params![],
+            )
+            .map_err(|e| {
+                DataBaseError::ConfigError(format!(
+                    "Invalid query of view \"{}\": {}",
+                    view_config.name, e
+                ))
+            })?;
+        let (columns, _) = get_table_schema(&scratch, &view_config.name)?;
+
+        let primary_key = view_config.primary_key.clone().unwrap_or_default();
+        if !primary_key.is_empty() && !columns.iter().any(|(name, _)| *name == primary_key) {
+            return Err(DataBaseError::ConfigError(format!(
+                "Primary key \"{}\" of view \"{}\" is not one of its columns",
+                primary_key, view_config.name
+            )));
+        }
+
+        views.push(Table {
+            name: view_config.name.clone(),
+            columns,
+            primary_key,
+            indexes: Vec::new(),
+            foreign_keys: Vec::new(),
+            view: Some(select),
+            full_text: Vec::new(),
+            soft_delete: false,
+            column_docs: Vec::new(),
+        });
+    }
+
+    Ok(views)
+}
+
+fn create_table_query(table: &Table) -> String {
+    let mut col_defs = get_col_defs(&table.columns);
+    if table.soft_delete {
+        col_defs.push(format!("{} TEXT", qr::quote_ident(DELETED_AT)));
+    }
+
+    let mut q = qr::CreateTable::new(&table.name).columns(&col_defs);
+    for fkey in &table.foreign_keys {
+        q = q.foreign_key(fkey);
+    }
+
+    q.to_query()
+}
+
 fn parse_columns(
     table_config: &TableConfig,
 ) -> Result<(Vec<(String, String)>, String), DataBaseError> {
+    match (
+        table_config.columns_dtypes.is_empty(),
+        table_config.columns.is_empty(),
+    ) {
+        (true, true) => {
+            return Err(DataBaseError::ConfigError(format!(
+                "Table \"{}\" has no columns",
+                table_config.name
+            )))
+        }
+        (false, false) => {
+            return Err(DataBaseError::ConfigError(format!(
+                "Columns of table \"{}\" are given both in \"columns_dtypes\" and \"columns\"",
+                table_config.name
+            )))
+        }
+        (true, false) => return parse_column_tables(table_config),
+        (false, true) => {}
+    }
+
     let mut cols_defs: Vec<(String, String)> = Vec::new();
     let mut pkey = String::new();
     for col_def in &table_config.columns_dtypes {
@@ -281,7 +1263,12 @@ fn parse_columns(
                 "Syntax error at definitions of columns".to_string(),
             ));
         }
-        // add checks of types
+        if let Some(err) = column_problems(table_config, &col_def[0], &col_def[1])
+            .into_iter()
+            .next()
+        {
+            return Err(err);
+        }
         if col_def[1].contains("PRIMARY KEY") {
             pkey = col_def[0].clone();
         }
@@ -292,7 +1279,327 @@ fn parse_columns(
     Ok((cols_defs, pkey))
 }
 
-fn get_col_names(cols_defs: &Vec<(String, String)>) -> Vec<String> {
+// `[[tables.columns]]` form, constraints are appended to the type
+fn parse_column_tables(
+    table_config: &TableConfig,
+) -> Result<(Vec<(String, String)>, String), DataBaseError> {
+    let mut cols_defs: Vec<(String, String)> = Vec::new();
+    let mut pkey = String::new();
+    for column in &table_config.columns {
+        if let Some(err) = column_problems(table_config, &column.name, &column.dtype)
+            .into_iter()
+            .next()
+        {
+            return Err(err);
+        }
+
+        let mut definition = column.dtype.trim().to_string();
+        if column.primary_key {
+            if !pkey.is_empty() {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Table \"{}\" has more than one primary key",
+                    table_config.name
+                )));
+            }
+            definition.push_str(" PRIMARY KEY");
+            pkey = column.name.clone();
+        }
+        if !column.nullable {
+            definition.push_str(" NOT NULL");
+        }
+        if column.unique {
+            definition.push_str(" UNIQUE");
+        }
+        if let Some(default) = &column.default {
+            let value = match default {
+                toml::Value::String(s) if s.starts_with('(') && s.ends_with(')') => s.clone(),
+                toml::Value::String(s) => qr::quote_literal(s),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => format!("{:?}", f),
+                toml::Value::Boolean(b) => (*b as i32).to_string(),
+                _ => {
+                    return Err(DataBaseError::ConfigError(format!(
+                        "Default of column \"{}\" must be a string, number or boolean",
+                        column.name
+                    )))
+                }
+            };
+            definition.push_str(&format!(" DEFAULT {}", value));
+        }
+
+        cols_defs.push((column.name.clone(), definition));
+    }
+
+    Ok((cols_defs, pkey))
+}
+
+// Checks of a column shared by loading of configs and `config check`: the loader stops at the
+// first problem, the check reports all of them
+pub(crate) fn column_problems(
+    table_config: &TableConfig,
+    name: &str,
+    dtype: &str,
+) -> Vec<DataBaseError> {
+    let mut problems = Vec::new();
+    if let Err(err) = validate_identifier("column", name) {
+        problems.push(err);
+    }
+    if dtype.trim().is_empty() {
+        problems.push(DataBaseError::ConfigError(format!(
+            "Column \"{}\" of table \"{}\" has no type",
+            name, table_config.name
+        )));
+    }
+    if table_config.soft_delete && name == DELETED_AT {
+        problems.push(DataBaseError::ConfigError(format!(
+            "Column \"{}\" of table \"{}\" is reserved for soft delete",
+            DELETED_AT, table_config.name
+        )));
+    }
+
+    problems
+}
+
+// names in `refs` that are not columns of the table, with their positions
+pub(crate) fn unknown_columns(
+    table_name: &str,
+    kind: &str,
+    refs: &[String],
+    columns: &[String],
+) -> Vec<(usize, DataBaseError)> {
+    refs.iter()
+        .enumerate()
+        .filter(|(_, col)| !columns.contains(col))
+        .map(|(idx, col)| {
+            let err = DataBaseError::ConfigError(format!(
+                "{} of table \"{}\" refers to unknown column \"{}\"",
+                kind, table_name, col
+            ));
+            (idx, err)
+        })
+        .collect()
+}
+
+fn parse_column_docs(table_config: &TableConfig) -> Vec<ColumnDoc> {
+    table_config
+        .columns
+        .iter()
+        .filter(|c| c.label.is_some() || c.description.is_some())
+        .map(|c| ColumnDoc {
+            column: c.name.clone(),
+            label: c.label.clone(),
+            description: c.description.clone(),
+        })
+        .collect()
+}
+
+fn parse_indexes(
+    table_config: &TableConfig,
+    columns: &[(String, String)],
+) -> Result<Vec<Index>, DataBaseError> {
+    let mut indexes: Vec<Index> = Vec::new();
+    for index_config in &table_config.indexes {
+        if index_config.columns.is_empty() {
+            return Err(DataBaseError::ConfigError(format!(
+                "Index of table \"{}\" has no columns",
+                table_config.name
+            )));
+        }
+        if let Some((_, err)) = unknown_columns(
+            &table_config.name,
+            "Index",
+            &index_config.columns,
+            &get_col_names(columns),
+        )
+        .into_iter()
+        .next()
+        {
+            return Err(err);
+        }
+
+        // default name is derived from table and columns, e.g. idx_Table_col1_col2
+        let name = index_config.name.clone().unwrap_or(format!(
+            "idx_{}_{}",
+            table_config.name,
+            index_config.columns.join("_")
+        ));
+        validate_identifier("index", &name)?;
+
+        indexes.push(Index {
+            name,
+            columns: index_config.columns.clone(),
+            unique: index_config.unique,
+            condition: index_config.condition.clone(),
+        });
+    }
+
+    Ok(indexes)
+}
+
+fn parse_foreign_keys(
+    table_config: &TableConfig,
+    tables: &[Table],
+) -> Result<Vec<ForeignKey>, DataBaseError> {
+    let mut fkeys: Vec<ForeignKey> = Vec::new();
+    let this_table = tables
+        .iter()
+        .find(|t| t.name == table_config.name)
+        .expect("table is parsed before its foreign keys");
+
+    for fkey_config in &table_config.foreign_keys {
+        if !this_table
+            .columns
+            .iter()
+            .any(|(name, _)| *name == fkey_config.column)
+        {
+            return Err(DataBaseError::ConfigError(format!(
+                "Foreign key of table \"{}\" refers to unknown column \"{}\"",
+                table_config.name, fkey_config.column
+            )));
+        }
+
+        let ref_table = match tables.iter().find(|t| t.name == fkey_config.references) {
+            Some(t) => t,
+            None => {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Foreign key \"{}.{}\" references unknown table \"{}\"",
+                    table_config.name, fkey_config.column, fkey_config.references
+                )))
+            }
+        };
+
+        let ref_column = match &fkey_config.references_column {
+            Some(col) => col.clone(),
+            None if !ref_table.primary_key.is_empty() => ref_table.primary_key.clone(),
+            None => {
+                return Err(DataBaseError::ConfigError(format!(
+                "Foreign key \"{}.{}\" does not name a column and table \"{}\" has no primary key",
+                table_config.name, fkey_config.column, ref_table.name
+            )))
+            }
+        };
+        if !ref_table
+            .columns
+            .iter()
+            .any(|(name, _)| *name == ref_column)
+        {
+            return Err(DataBaseError::ConfigError(format!(
+                "Foreign key \"{}.{}\" references unknown column \"{}.{}\"",
+                table_config.name, fkey_config.column, ref_table.name, ref_column
+            )));
+        }
+
+        fkeys.push(ForeignKey {
+            column: fkey_config.column.clone(),
+            ref_table: ref_table.name.clone(),
+            ref_column,
+            on_delete: fkey_config.on_delete.clone(),
+            on_update: fkey_config.on_update.clone(),
+        });
+    }
+
+    Ok(fkeys)
+}
+
+// sorts tables so that each one comes after the tables it references
+fn order_by_dependencies(tables: &[Table]) -> Result<Vec<&Table>, DataBaseError> {
+    let mut ordered: Vec<&Table> = Vec::new();
+    let mut remaining: Vec<&Table> = tables.iter().collect();
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|t| {
+            t.foreign_keys.iter().all(|fkey| {
+                fkey.ref_table == t.name || ordered.iter().any(|o| o.name == fkey.ref_table)
+            })
+        });
+
+        match ready {
+            Some(idx) => ordered.push(remaining.remove(idx)),
+            None => {
+                let names: Vec<&str> = remaining.iter().map(|t| t.name.as_str()).collect();
+                return Err(DataBaseError::ConfigError(format!(
+                    "Circular references between tables: {}",
+                    names.join(", ")
+                )));
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+// distinguishes violated references from other sqlite failures
+fn constraint_error(table_name: &str, operation: Operation, err: rusqlite::Error) -> DataBaseError {
+    match err {
+        rusqlite::Error::SqliteFailure(e, _)
+            if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY =>
+        {
+            DataBaseError::ForeignKeyViolation(match operation {
+                Operation::Delete | Operation::Purge => format!(
+                    "Row of \"{}\" cannot be deleted, other rows refer to it",
+                    table_name
+                ),
+                _ => format!(
+                    "{} of \"{}\" refers to a row that does not exist",
+                    operation.as_str(),
+                    table_name
+                ),
+            })
+        }
+        _ => DataBaseError::from(err).context(format!(
+            "{} of \"{}\"",
+            operation.as_str(),
+            table_name
+        )),
+    }
+}
+
+// primary key of a row about to be inserted, None if it is assigned by sqlite
+fn inserted_key(table: &Table, columns: &[String], vals: &[DataType]) -> Option<DataType> {
+    let idx = columns.iter().position(|c| *c == table.primary_key)?;
+    match vals.get(idx) {
+        Some(DataType::Null) | Some(DataType::Param(_)) | None => None,
+        Some(val) => Some(val.clone()),
+    }
+}
+
+// names are quoted in queries, so spaces and reserved words are fine. Names reserved by sqlite,
+// control characters and dots (used to qualify columns with a table) are not
+fn validate_identifier(kind: &str, name: &str) -> Result<(), DataBaseError> {
+    let problem = if name.trim().is_empty() {
+        Some("name is empty")
+    } else if name.trim() != name {
+        Some("name starts or ends with whitespace")
+    } else if name.chars().any(|c| c.is_control()) {
+        Some("name contains control characters")
+    } else if name.to_lowercase().starts_with("sqlite_") {
+        Some("names starting with \"sqlite_\" are reserved by SQLite")
+    } else if kind != "index" && name.contains('.') {
+        // a dot separates the table from the column in queries
+        Some("names of tables, views and columns cannot contain dots")
+    } else {
+        None
+    };
+
+    match problem {
+        Some(problem) => Err(DataBaseError::ConfigError(format!(
+            "Illegal {} name \"{}\": {}",
+            kind,
+            name.escape_debug(),
+            problem
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn get_col_defs(cols_defs: &[(String, String)]) -> Vec<String> {
+    cols_defs
+        .iter()
+        .map(|(name, dtype)| format!("{} {}", qr::quote_ident(name), dtype))
+        .collect()
+}
+
+fn get_col_names(cols_defs: &[(String, String)]) -> Vec<String> {
     let mut cols = Vec::new();
     for col_def in cols_defs.iter() {
         cols.push(col_def.0.to_string());
@@ -305,45 +1612,243 @@ fn get_table_schema(
     conn: &Connection,
     table_name: &str,
 ) -> Result<(Vec<(String, String)>, String)> {
-    let query = format!("PRAGMA table_info('{}');", table_name);
+    let query = format!("PRAGMA table_info({});", qr::quote_ident(table_name));
     let mut stmt = conn.prepare(&query)?;
     let schema_iter = stmt.query_map([], |row| {
         Ok((
-            row.get::<_, String>(1)?,  // Column name
-            row.get::<_, String>(2)?,  // Column type
-            row.get::<_, i32>(5)? > 0, // Primary key flag (pk > 0 means it’s a primary key)
+            row.get::<_, String>(1)?,         // Column name
+            row.get::<_, String>(2)?,         // Column type
+            row.get::<_, i32>(3)? > 0,        // NOT NULL flag
+            row.get::<_, Option<String>>(4)?, // Default value as SQL text
+            row.get::<_, i32>(5)? > 0,        // Primary key flag (pk > 0 means it’s a primary key)
         ))
     })?;
+    let columns: Vec<_> = schema_iter.collect::<Result<_>>()?;
+    let unique = get_unique_columns(conn, table_name)?;
+    // a key of several columns cannot be written in column definitions
+    let single_key = columns.iter().filter(|col| col.4).count() == 1;
 
+    // definitions are rebuilt the way `parse_column_tables` writes them, so dumps of tables
+    // read from a file keep their constraints. Keys and UNIQUE of several columns are not kept
     let mut schema = Vec::new();
     let mut pk = String::new();
-    for column in schema_iter {
-        let (col_name, col_type, pk_status) = column?;
+    for (col_name, col_type, not_null, default, pk_status) in columns {
+        let mut definition = col_type;
+        if pk_status && single_key {
+            definition.push_str(" PRIMARY KEY");
+        }
+        if not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if unique.contains(&col_name) {
+            definition.push_str(" UNIQUE");
+        }
+        if let Some(default) = default {
+            definition.push_str(&format!(" DEFAULT {}", default_sql(&default)));
+        }
 
-        schema.push((col_name.clone(), col_type.clone()));
+        schema.push((col_name.clone(), definition.trim().to_string()));
         if pk_status {
-            pk = col_name.clone();
+            pk = col_name;
         }
     }
 
     Ok((schema, pk))
 }
 
+// table_info gives default expressions without their parentheses, only literals can go bare
+fn default_sql(default: &str) -> String {
+    let upper = default.to_uppercase();
+    let literal = default.starts_with('\'')
+        || default.parse::<f64>().is_ok()
+        || (upper.starts_with("X'") && default.ends_with('\''))
+        || [
+            "NULL",
+            "TRUE",
+            "FALSE",
+            "CURRENT_TIME",
+            "CURRENT_DATE",
+            "CURRENT_TIMESTAMP",
+        ]
+        .contains(&upper.as_str());
+
+    if literal {
+        default.to_string()
+    } else {
+        format!("({})", default)
+    }
+}
+
+// columns with a UNIQUE constraint of their own
+fn get_unique_columns(conn: &Connection, table_name: &str) -> Result<Vec<String>> {
+    let query = format!("PRAGMA index_list({});", qr::quote_ident(table_name));
+    let mut stmt = conn.prepare(&query)?;
+    let names: Vec<String> = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(1)?, row.get::<_, String>(3)?)) // Index name and origin
+        })?
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, origin)| origin == "u")
+        .map(|(name, _)| name)
+        .collect();
+
+    let mut unique = Vec::new();
+    for name in names {
+        let mut info = conn.prepare(&format!("PRAGMA index_info({});", qr::quote_ident(&name)))?;
+        let mut columns: Vec<String> = info
+            .query_map([], |row| row.get::<_, String>(2))?
+            .collect::<Result<_>>()?;
+        if columns.len() == 1 {
+            unique.append(&mut columns);
+        }
+    }
+
+    Ok(unique)
+}
+
+// only indexes created explicitly are listed, automatic ones (PRIMARY KEY, UNIQUE) are skipped.
+// WHERE part of partial indexes is not restored
+fn get_table_indexes(conn: &Connection, table_name: &str) -> Result<Vec<Index>> {
+    let query = format!("PRAGMA index_list({});", qr::quote_ident(table_name));
+    let mut stmt = conn.prepare(&query)?;
+    let index_iter = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(1)?,  // Index name
+            row.get::<_, i32>(2)? > 0, // Unique flag
+            row.get::<_, String>(3)?,  // Origin: "c" for CREATE INDEX
+        ))
+    })?;
+
+    let mut indexes = Vec::new();
+    for index in index_iter {
+        let (name, unique, origin) = index?;
+        if origin != "c" {
+            continue;
+        }
+
+        let mut info = conn.prepare(&format!("PRAGMA index_info({});", qr::quote_ident(&name)))?;
+        let columns: Result<Vec<String>> =
+            info.query_map([], |row| row.get::<_, String>(2))?.collect();
+
+        indexes.push(Index {
+            name,
+            columns: columns?,
+            unique,
+            condition: None,
+        });
+    }
+
+    Ok(indexes)
+}
+
+fn get_table_foreign_keys(conn: &Connection, table_name: &str) -> Result<Vec<ForeignKey>> {
+    let query = format!("PRAGMA foreign_key_list({});", qr::quote_ident(table_name));
+    let mut stmt = conn.prepare(&query)?;
+    let fkey_iter = stmt.query_map([], |row| {
+        let on_update: String = row.get(5)?;
+        let on_delete: String = row.get(6)?;
+        Ok(ForeignKey {
+            ref_table: row.get(2)?,
+            column: row.get(3)?,
+            ref_column: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            // "NO ACTION" is the default behaviour
+            on_update: Some(on_update).filter(|a| a != "NO ACTION"),
+            on_delete: Some(on_delete).filter(|a| a != "NO ACTION"),
+        })
+    })?;
+
+    fkey_iter.collect()
+}
+
+// cuts the SELECT statement out of `CREATE VIEW name AS SELECT ...` kept in sqlite_master,
+// skipping quoted names which may contain " AS " themselves
+fn view_select(create_sql: &str) -> String {
+    let mut quote: Option<char> = None;
+    let chars: Vec<(usize, char)> = create_sql.char_indices().collect();
+
+    for (pos, &(idx, ch)) in chars.iter().enumerate() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => {}
+            None if ch == '"' || ch == '`' || ch == '\'' => quote = Some(ch),
+            None if ch == '[' => quote = Some(']'),
+            None => {
+                let is_as = ch.eq_ignore_ascii_case(&'a')
+                    && chars
+                        .get(pos + 1)
+                        .is_some_and(|c| c.1.eq_ignore_ascii_case(&'s'))
+                    && pos > 0
+                    && chars[pos - 1].1.is_whitespace()
+                    && chars.get(pos + 2).is_some_and(|c| c.1.is_whitespace());
+                if is_as {
+                    return create_sql[idx + 2..].trim().to_string();
+                }
+            }
+        }
+    }
+
+    create_sql.to_string()
+}
+
 fn get_tables_from_file(connection: &Connection) -> Result<Vec<Table>> {
-    let mut query = connection.prepare("SELECT name FROM sqlite_master WHERE type='table';")?;
-    let table_iter: Result<Vec<String>> = query
-        .query_map([], |row| Ok(row.get::<_, String>(0)?))?
+    let mut query = connection.prepare(
+        "SELECT name, type, sql FROM sqlite_master \
+         WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%';",
+    )?;
+    let table_iter: Result<Vec<(String, String, String)>> = query
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
         .collect();
+    let entries = table_iter?;
+
+    // FTS5 tables and their shadow tables (<name>_data, <name>_idx...) are not user tables
+    let virtual_tables: Vec<&String> = entries
+        .iter()
+        .filter(|(_, _, sql)| sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE"))
+        .map(|(name, _, _)| name)
+        .collect();
+    let is_internal = |name: &str| {
+        virtual_tables
+            .iter()
+            .any(|vt| name == vt.as_str() || name.starts_with(&format!("{}_", vt)))
+    };
 
     let mut tables: Vec<Table> = Vec::new();
 
-    let tables_names: Vec<String> = table_iter?;
-    for tab_name in tables_names {
-        let (cols, pk) = get_table_schema(&connection, &tab_name)?;
+    for (tab_name, kind, sql) in entries.iter().cloned() {
+        if is_internal(&tab_name) || tab_name == AUDIT_TABLE {
+            continue;
+        }
+
+        let fts_name = qr::full_text_table(&tab_name);
+        let full_text = if virtual_tables.iter().any(|vt| **vt == fts_name) {
+            get_col_names(&get_table_schema(connection, &fts_name)?.0)
+        } else {
+            Vec::new()
+        };
+
+        let (mut cols, pk) = get_table_schema(connection, &tab_name)?;
+        // `deleted_at` column is the mark of soft delete and not shown as ordinary column
+        let soft_delete = kind == "table" && cols.iter().any(|(name, _)| name == DELETED_AT);
+        if soft_delete {
+            cols.retain(|(name, _)| name != DELETED_AT);
+        }
+        let indexes = get_table_indexes(connection, &tab_name)?;
+        let foreign_keys = get_table_foreign_keys(connection, &tab_name)?;
         tables.push(Table {
             name: tab_name,
             columns: cols,
             primary_key: pk,
+            indexes,
+            foreign_keys,
+            view: if kind == "view" {
+                Some(view_select(&sql))
+            } else {
+                None
+            },
+            full_text,
+            soft_delete,
+            column_docs: Vec::new(),
         })
     }
 
@@ -0,0 +1,294 @@
+// Async facade of `DataBase` for services running on an async runtime. The database is moved
+// to a thread of its own and every call sends a job there, so blocking sqlite work never runs
+// on the runtime. Calls return futures resolved when the job is done; they do not depend on
+// a particular runtime and work with tokio as well as any other executor.
+
+use super::configuration::DatabaseConfig;
+use super::definitions::{DataBase, DataType};
+use super::errors::DataBaseError;
+use crate::query_builder as qr;
+
+use std::future::Future;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+type Job = Box<dyn FnOnce(&mut DataBase) + Send>;
+
+enum Message {
+    Run(Job),
+    Close(Completer<()>), // completed when the database is closed
+}
+
+/// Handle of the database thread. Clones share the thread, it stops when all of them are dropped
+/// or `close` is called
+#[derive(Clone)]
+pub struct AsyncDataBase {
+    jobs: Sender<Message>,
+}
+
+/// Result of a call, ready when the database thread has run it
+pub struct Reply<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+struct Slot<T> {
+    result: Option<Result<T, DataBaseError>>,
+    waker: Option<Waker>,
+}
+
+// fills the slot of a reply, with an error if the job panics or is dropped without running
+struct Completer<T> {
+    slot: Option<Arc<Mutex<Slot<T>>>>,
+}
+
+impl AsyncDataBase {
+    pub fn new(db: DataBase) -> Self {
+        let (jobs, received) = mpsc::channel::<Message>();
+        thread::Builder::new()
+            .name("lab_db".to_string())
+            .spawn(move || {
+                let mut db = db;
+                for message in received {
+                    let job = match message {
+                        Message::Run(job) => job,
+                        // jobs sent afterwards are dropped with the channel and fail
+                        Message::Close(completer) => {
+                            drop(db);
+                            completer.complete(Ok(()));
+                            return;
+                        }
+                    };
+                    // a panicking job completes its reply with an error, the thread keeps
+                    // serving the others
+                    if panic::catch_unwind(AssertUnwindSafe(|| job(&mut db))).is_err()
+                        && !db.connection.is_autocommit()
+                    {
+                        // transaction of the job is left open by the panic
+                        let _ = db.connection.execute_batch("ROLLBACK;");
+                    }
+                }
+            })
+            .expect("failed to spawn database thread");
+
+        AsyncDataBase { jobs }
+    }
+
+    // opening is blocking as well, it is done on another thread
+    pub fn open(config: DatabaseConfig) -> Reply<AsyncDataBase> {
+        let (reply, completer) = Reply::new();
+        thread::spawn(move || {
+            completer.complete(DataBase::from_database_config(&config).map(AsyncDataBase::new));
+        });
+
+        reply
+    }
+
+    // runs any work with the database on its thread
+    pub fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut DataBase) -> Result<T, DataBaseError> + Send + 'static,
+    ) -> Reply<T> {
+        let (reply, completer) = Reply::new();
+        // if the thread has stopped the job is dropped and completes the reply with an error
+        let _ = self
+            .jobs
+            .send(Message::Run(Box::new(move |db: &mut DataBase| {
+                completer.complete(f(db))
+            })));
+
+        reply
+    }
+
+    // stops the thread after the calls made before, the database is closed when the reply is
+    // ready. Later calls of every clone fail
+    pub fn close(&self) -> Reply<()> {
+        let (reply, completer) = Reply::new();
+        let _ = self.jobs.send(Message::Close(completer));
+
+        reply
+    }
+
+    // primary key of the new row, see `DataBase::insert_values`
+    pub fn insert(&self, table_name: &str, values: Vec<(String, DataType)>) -> Reply<DataType> {
+        let table_name = table_name.to_string();
+        self.call(move |db| db.insert_values(&table_name, &values))
+    }
+
+    pub fn select(&self, select: qr::Select) -> Reply<Vec<Vec<DataType>>> {
+        self.call(move |db| db.select(&select))
+    }
+
+    pub fn get(&self, table_name: &str, key: DataType) -> Reply<Option<Vec<DataType>>> {
+        let table_name = table_name.to_string();
+        self.call(move |db| db.get(&table_name, &key))
+    }
+
+    pub fn update(
+        &self,
+        table_name: &str,
+        key: DataType,
+        values: Vec<(String, DataType)>,
+    ) -> Reply<bool> {
+        let table_name = table_name.to_string();
+        self.call(move |db| db.update(&table_name, &key, &values))
+    }
+
+    pub fn delete(&self, table_name: &str, key: DataType) -> Reply<bool> {
+        let table_name = table_name.to_string();
+        self.call(move |db| db.delete(&table_name, &key))
+    }
+
+    // `f` runs in a transaction on the database thread, see `DataBase::transaction`
+    pub fn transaction<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&DataBase) -> Result<T, DataBaseError> + Send + 'static,
+    ) -> Reply<T> {
+        self.call(move |db| db.transaction(f))
+    }
+}
+
+impl<T> Reply<T> {
+    fn new() -> (Reply<T>, Completer<T>) {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+        (Reply { slot: slot.clone() }, Completer { slot: Some(slot) })
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T, DataBaseError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap_or_else(|err| err.into_inner());
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Completer<T> {
+    fn complete(mut self, result: Result<T, DataBaseError>) {
+        if let Some(slot) = self.slot.take() {
+            fill(&slot, result);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            let message = if thread::panicking() {
+                "database call panicked"
+            } else {
+                "database thread has stopped"
+            };
+            fill(
+                &slot,
+                Err(DataBaseError::IoError(io::Error::other(message))),
+            );
+        }
+    }
+}
+
+fn fill<T>(slot: &Mutex<Slot<T>>, result: Result<T, DataBaseError>) {
+    let mut slot = slot.lock().unwrap_or_else(|err| err.into_inner());
+    slot.result = Some(result);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::definitions::DataType;
+
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // runs a future on the current thread, enough for replies
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    fn database() -> AsyncDataBase {
+        let db = DataBase::from_file(":memory:").unwrap();
+        db.connection
+            .execute_batch("CREATE TABLE T (id INTEGER PRIMARY KEY, name TEXT);")
+            .unwrap();
+        AsyncDataBase::new(db)
+    }
+
+    fn count(db: &AsyncDataBase) -> Result<i64, DataBaseError> {
+        block_on(db.call(|db| {
+            let count = db
+                .connection
+                .query_row("SELECT COUNT(*) FROM T;", [], |row| row.get(0))?;
+            Ok(count)
+        }))
+    }
+
+    #[test]
+    fn panicking_job_fails_alone() {
+        let db = database();
+        let result: Result<(), _> = block_on(db.call(|_| panic!("job failed")));
+        assert_eq!(
+            result.unwrap_err().report(),
+            "I/O error: database call panicked"
+        );
+
+        // the thread is still serving, the open transaction of a panic is rolled back
+        let result: Result<(), _> = block_on(db.clone().call(|db| {
+            db.connection
+                .execute_batch("BEGIN; INSERT INTO T (name) VALUES ('lost');")?;
+            panic!("job failed in transaction")
+        }));
+        assert!(result.is_err());
+        assert_eq!(count(&db).unwrap(), 0);
+    }
+
+    #[test]
+    fn calls_after_close_fail() {
+        let db = database();
+        let clone = db.clone();
+        block_on(db.call(|db| {
+            db.connection
+                .execute_batch("INSERT INTO T (name) VALUES ('kept');")?;
+            Ok(())
+        }))
+        .unwrap();
+        assert_eq!(count(&clone).unwrap(), 1);
+
+        block_on(db.close()).unwrap();
+        let err = count(&clone).unwrap_err();
+        assert_eq!(err.report(), "I/O error: database thread has stopped");
+        assert!(block_on(db.get("T", DataType::Int(1))).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+// Optional log of changes made through `DataBase` methods. Every insert, update and delete of a
+// row is recorded into `_audit` together with the row before and after the change as JSON.
+
+use super::definitions::{DataBase, DataType, Table};
+use super::errors::DataBaseError;
+use super::serialization::to_json;
+use crate::query_builder as qr;
+use crate::query_builder::ToQuery;
+
+use rusqlite::params;
+use serde_json::{Map, Value as JsonValue};
+use std::env;
+
+pub const AUDIT_TABLE: &str = "_audit";
+
+const CREATE_AUDIT_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS \"_audit\" (
+    id INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    user TEXT NOT NULL,
+    table_name TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    primary_key TEXT NOT NULL,
+    old_values TEXT,
+    new_values TEXT
+);
+CREATE INDEX IF NOT EXISTS \"_audit_record\" ON \"_audit\" (table_name, primary_key);";
+
+/// Settings of the audit log, `user` is recorded as the author of changes
+#[derive(Clone, Debug)]
+pub struct Audit {
+    pub user: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+    Restore, // soft deleted row brought back
+    Purge,   // soft deleted row removed for good
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Insert => "INSERT",
+            Operation::Update => "UPDATE",
+            Operation::Delete => "DELETE",
+            Operation::Restore => "RESTORE",
+            Operation::Purge => "PURGE",
+        }
+    }
+}
+
+/// One recorded change. Values are JSON objects of the row, `None` before insert and after delete
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: String,
+    pub user: String,
+    pub table_name: String,
+    pub operation: String,
+    pub primary_key: String,
+    pub old_values: Option<String>,
+    pub new_values: Option<String>,
+}
+
+// name of the OS user running the program
+pub fn default_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+impl DataBase {
+    pub fn enable_audit(&mut self, user: &str) -> Result<(), DataBaseError> {
+        self.connection
+            .execute_batch(CREATE_AUDIT_TABLE)
+            .map_err(DataBaseError::from)?;
+        self.audit = Some(Audit {
+            user: user.to_string(),
+        });
+
+        Ok(())
+    }
+
+    // stops recording, already recorded history is kept
+    pub fn disable_audit(&mut self) {
+        self.audit = None;
+    }
+
+    // changes of a single record, oldest first
+    pub fn history(
+        &self,
+        table_name: &str,
+        primary_key: &DataType,
+    ) -> Result<Vec<AuditEntry>, DataBaseError> {
+        let q = qr::Select::new(AUDIT_TABLE)
+            .conditions("table_name = ? AND primary_key = ?")
+            .order_by("id", qr::Order::Asc)
+            .to_query();
+        let mut stmt = self.connection.prepare(&q).map_err(DataBaseError::from)?;
+        let entries = stmt
+            .query_map(params![table_name, primary_key.to_string()], |row| {
+                Ok(AuditEntry {
+                    id: row.get("id")?,
+                    timestamp: row.get("timestamp")?,
+                    user: row.get("user")?,
+                    table_name: row.get("table_name")?,
+                    operation: row.get("operation")?,
+                    primary_key: row.get("primary_key")?,
+                    old_values: row.get("old_values")?,
+                    new_values: row.get("new_values")?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<AuditEntry>>>()
+            .map_err(DataBaseError::from)?;
+
+        Ok(entries)
+    }
+
+    // Runs `change` of a single row and records it. `old_key` identifies the row before the
+    // change, `new_key` after it; new key of an insert is the last inserted rowid if not given.
+    // Change and its record are made atomically in a savepoint.
+    pub(crate) fn audited(
+        &self,
+        table: &Table,
+        operation: Operation,
+        old_key: Option<&DataType>,
+        new_key: Option<&DataType>,
+        change: impl FnOnce() -> Result<usize, DataBaseError>,
+    ) -> Result<usize, DataBaseError> {
+        let audit = match &self.audit {
+            Some(audit) => audit,
+            None => return change(),
+        };
+
+        self.connection.execute_batch("SAVEPOINT audited_change;")?;
+        let result = self.record_change(audit, table, operation, old_key, new_key, change);
+        match result {
+            Ok(_) => self.connection.execute_batch("RELEASE audited_change;")?,
+            Err(_) => self
+                .connection
+                .execute_batch("ROLLBACK TO audited_change; RELEASE audited_change;")?,
+        }
+
+        result
+    }
+
+    fn record_change(
+        &self,
+        audit: &Audit,
+        table: &Table,
+        operation: Operation,
+        old_key: Option<&DataType>,
+        new_key: Option<&DataType>,
+        change: impl FnOnce() -> Result<usize, DataBaseError>,
+    ) -> Result<usize, DataBaseError> {
+        let old_values = match old_key {
+            Some(key) => self.row_json(table, key)?,
+            None => None,
+        };
+
+        let changed = change()?;
+        if changed == 0 {
+            return Ok(0);
+        }
+
+        let new_key = match (operation, new_key) {
+            (Operation::Delete | Operation::Purge, _) => None,
+            (_, Some(key)) => Some(key.clone()),
+            (_, None) => Some(DataType::Int(self.connection.last_insert_rowid())),
+        };
+        let new_values = match &new_key {
+            Some(key) => self.row_json(table, key)?,
+            None => None,
+        };
+        let key = new_key
+            .as_ref()
+            .or(old_key)
+            .cloned()
+            .unwrap_or(DataType::Null);
+
+        let mut stmt = self.connection.prepare_cached(
+            "INSERT INTO \"_audit\" (user, table_name, operation, primary_key, old_values, new_values) \
+             VALUES (?, ?, ?, ?, ?, ?);",
+        )?;
+        stmt.execute(params![
+            audit.user,
+            table.name,
+            operation.as_str(),
+            key.to_string(),
+            old_values,
+            new_values
+        ])?;
+
+        Ok(changed)
+    }
+
+    // row as JSON object of column names and values, None if there is no such row
+    fn row_json(&self, table: &Table, key: &DataType) -> Result<Option<String>, DataBaseError> {
+        let q = qr::Select::new(&table.name)
+            .conditions(&format!("{} = ?", qr::quote_ident(key_column(table))))
+            .to_query();
+        let mut stmt = self
+            .connection
+            .prepare_cached(&q)
+            .map_err(DataBaseError::from)?;
+        let mut rows = stmt.query([key])?;
+
+        let row = match rows.next()? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let mut object = Map::new();
+        for (idx, (col, _)) in table.columns.iter().enumerate() {
+            object.insert(col.clone(), to_json(row.get::<usize, DataType>(idx)?));
+        }
+
+        Ok(Some(JsonValue::Object(object).to_string()))
+    }
+}
+
+// column identifying rows of the table
+pub fn key_column(table: &Table) -> &str {
+    if table.primary_key.is_empty() {
+        "rowid"
+    } else {
+        table.primary_key.as_str()
+    }
+}
@@ -0,0 +1,159 @@
+// Consistent copies of a database that may be in use, made with sqlite online backup API.
+// Backups made by `backup_into` are named `<db name>-<YYYYMMDD-HHMMSS-mmm>.db` with milliseconds,
+// so sorting their names sorts them by time.
+
+use super::definitions::DataBase;
+use super::errors::{Context, DataBaseError};
+
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{ffi, Connection};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const PAGES_PER_STEP: i32 = 100;
+// other connections holding locks are waited for this many times before giving up
+const MAX_BUSY_STEPS: u32 = 50;
+const BUSY_PAUSE: Duration = Duration::from_millis(100);
+
+impl DataBase {
+    // `progress` is called after every step with numbers of copied and total pages
+    pub fn backup_to(
+        &self,
+        path: &str,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(), DataBaseError> {
+        let mut target = Connection::open(path).context(|| format!("opening backup {}", path))?;
+        copy(&self.connection, &mut target, progress)
+    }
+
+    // replaces content of the database with the backup. Tables known to `DataBase` stay the same
+    pub fn restore_from(
+        &mut self,
+        path: &str,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(), DataBaseError> {
+        if !Path::new(path).is_file() {
+            return Err(DataBaseError::ConfigError(format!(
+                "Backup \"{}\" does not exist",
+                path
+            )));
+        }
+
+        let source = Connection::open(path).context(|| format!("opening backup {}", path))?;
+        copy(&source, &mut self.connection, progress)
+    }
+
+    // makes a timestamped backup in `dir` and removes older backups except the last `keep`
+    pub fn backup_into(
+        &self,
+        dir: &str,
+        keep: usize,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<PathBuf, DataBaseError> {
+        fs::create_dir_all(dir).context(|| format!("creating backup directory {}", dir))?;
+        // 'now' is the same moment for the whole statement
+        let now = "SELECT strftime('%Y%m%d-%H%M%S-', 'now') || substr(strftime('%f', 'now'), 4);";
+        let timestamp: String = self.connection.query_row(now, [], |row| row.get(0))?;
+        let path = Path::new(dir).join(format!("{}-{}.db", self.backup_prefix(), timestamp));
+        // another backup made in the same millisecond is not overwritten
+        if path.exists() {
+            return Err(DataBaseError::ConfigError(format!(
+                "Backup \"{}\" already exists",
+                path.display()
+            )));
+        }
+        self.backup_to(&path.to_string_lossy(), progress)?;
+
+        let backups = self.backups(dir)?;
+        let outdated = backups.len().saturating_sub(keep.max(1));
+        for old in &backups[..outdated] {
+            fs::remove_file(old).context(|| format!("removing old backup {}", old.display()))?;
+        }
+
+        Ok(path)
+    }
+
+    // backups of this database in `dir`, oldest first
+    pub fn backups(&self, dir: &str) -> Result<Vec<PathBuf>, DataBaseError> {
+        if !Path::new(dir).is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let prefix = format!("{}-", self.backup_prefix());
+        let mut backups: Vec<PathBuf> = Vec::new();
+        for entry in fs::read_dir(dir).context(|| format!("reading backup directory {}", dir))? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let stamp = name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".db"));
+            if stamp.is_some_and(is_timestamp) {
+                backups.push(path);
+            }
+        }
+        backups.sort();
+
+        Ok(backups)
+    }
+
+    // file name of the database without extension, `memory` for in-memory ones
+    fn backup_prefix(&self) -> String {
+        self.connection
+            .path()
+            .filter(|p| !p.is_empty())
+            .and_then(|p| Path::new(p).file_stem())
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "memory".to_string())
+    }
+}
+
+fn copy(
+    from: &Connection,
+    to: &mut Connection,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<(), DataBaseError> {
+    let backup = Backup::new(from, to)?;
+
+    let mut busy_steps = 0;
+    loop {
+        let result = backup.step(PAGES_PER_STEP)?;
+        let state = backup.progress();
+        progress(
+            (state.pagecount - state.remaining) as usize,
+            state.pagecount as usize,
+        );
+
+        match result {
+            StepResult::Done => return Ok(()),
+            StepResult::More => busy_steps = 0,
+            // database is locked by another connection, wait for it
+            _ => {
+                busy_steps += 1;
+                if busy_steps > MAX_BUSY_STEPS {
+                    return Err(DataBaseError::SqliteError(rusqlite::Error::SqliteFailure(
+                        ffi::Error::new(ffi::SQLITE_BUSY),
+                        Some("Database stays locked, backup is not finished".to_string()),
+                    )));
+                }
+                thread::sleep(BUSY_PAUSE);
+            }
+        }
+    }
+}
+
+// `YYYYMMDD-HHMMSS-mmm`, backups made before milliseconds were added have no `-mmm`
+fn is_timestamp(stamp: &str) -> bool {
+    (stamp.len() == 15 || stamp.len() == 19)
+        && stamp.char_indices().all(|(idx, c)| {
+            if idx == 8 || idx == 15 {
+                c == '-'
+            } else {
+                c.is_ascii_digit()
+            }
+        })
+}
@@ -1,41 +1,520 @@
-use serde::{Deserialize, Serialize};
+use super::audit::default_user;
+use super::errors::{Context, DataBaseError};
+use crate::query_builder::JoinKind;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::path::Path;
 use toml;
 
+// name of the database defined with `[database]`
+pub const DEFAULT_DATABASE: &str = "main";
+// profile used when none is given explicitly
+pub const PROFILE_ENV: &str = "LAB_DB_PROFILE";
+// capacity of the prepared statement cache of connections
+pub const DEFAULT_STATEMENT_CACHE: usize = 16;
+// path of a database kept in memory only
+pub const MEMORY_PATH: &str = ":memory:";
+
+/// Either a single `[database]` with top level `[[tables]]` and `[[views]]`, or several named
+/// `[[databases]]` each with its own `[[databases.tables]]` and `[[databases.views]]`
 #[derive(Debug, Deserialize)]
 pub struct Config {
-    pub database: DatabaseConfig,
+    pub database: Option<DatabaseConfig>,
+    #[serde(default)]
     pub tables: Vec<TableConfig>,
+    #[serde(default)]
+    pub views: Vec<ViewConfig>,
+    #[serde(default)]
+    pub databases: Vec<DatabaseConfig>,
+    #[serde(default)]
+    pub include: Vec<String>, // glob patterns of files with more tables and views
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DatabaseConfig {
-    pub path: String,
+    pub name: Option<String>, // required in `[[databases]]`
+    pub path: String,         // `${VAR}` is replaced with environment variable
+    #[serde(default)]
+    pub audit: bool, // record changes into `_audit` table
+    pub audit_user: Option<String>, // OS user by default
+    #[serde(default = "default_statement_cache")]
+    pub statement_cache: usize, // number of prepared statements kept for reuse, 0 disables
+    #[serde(flatten)]
+    pub connection: ConnectionOptions,
+    #[serde(default)]
+    pub tables: Vec<TableConfig>,
+    #[serde(default)]
+    pub views: Vec<ViewConfig>,
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+/// Settings applied to the connection right after it is opened, sqlite defaults if not given.
+/// Path `:memory:` opens a database that lives only as long as the connection
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConnectionOptions {
+    pub journal_mode: Option<JournalMode>,
+    pub synchronous: Option<Synchronous>,
+    pub busy_timeout: Option<u64>, // milliseconds to wait for locks of other connections
+    pub cache_size: Option<i64>,   // pages, or KiB if negative
+    #[serde(default = "default_true")]
+    pub foreign_keys: bool,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default = "default_true")]
+    pub create: bool, // create the file if it does not exist
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        ConnectionOptions {
+            journal_mode: None,
+            synchronous: None,
+            busy_timeout: None,
+            cache_size: None,
+            foreign_keys: true,
+            read_only: false,
+            create: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+impl JournalMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "delete",
+            JournalMode::Truncate => "truncate",
+            JournalMode::Persist => "persist",
+            JournalMode::Memory => "memory",
+            JournalMode::Wal => "wal",
+            JournalMode::Off => "off",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+impl Synchronous {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+/// Included file holds only tables and views
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludedConfig {
+    #[serde(default)]
+    pub tables: Vec<TableConfig>,
+    #[serde(default)]
+    pub views: Vec<ViewConfig>,
+}
+
+/// Overrides of database paths, e.g. `[profiles.test]` with a scratch database
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub path: Option<String>, // path of `[database]`
+    #[serde(default)]
+    pub paths: HashMap<String, String>, // paths of `[[databases]]` by name
 }
 
+/// Columns are given either as `columns_dtypes = [[name, type], ...]` or as `[[tables.columns]]`
 #[derive(Debug, Deserialize)]
 pub struct TableConfig {
     pub name: String,
+    #[serde(default)]
     pub columns_dtypes: Vec<Vec<String>>,
+    #[serde(default)]
+    pub columns: Vec<ColumnConfig>,
+    #[serde(default)]
+    pub indexes: Vec<IndexConfig>,
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKeyConfig>,
+    #[serde(default)]
+    pub full_text: Vec<String>, // columns indexed for full-text search
+    #[serde(default)]
+    pub soft_delete: bool, // keep deleted rows marked with `deleted_at` time
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub dtype: String,
+    #[serde(default)]
+    pub primary_key: bool,
+    #[serde(default = "default_true")]
+    pub nullable: bool,
+    pub default: Option<toml::Value>, // literal value, string in parentheses is an SQL expression
+    #[serde(default)]
+    pub unique: bool,
+    pub label: Option<String>,
+    pub description: Option<String>,
+}
+
+fn default_statement_cache() -> usize {
+    DEFAULT_STATEMENT_CACHE
 }
 
-pub fn print_config(config_path: &str) {
-    // Read the TOML configuration file to a string
-    let toml_content = fs::read_to_string(config_path).expect("Failed to read config file");
+fn default_true() -> bool {
+    true
+}
 
-    // Parse the TOML content
-    let config: Config = toml::from_str(&toml_content).expect("Failed to parse TOML");
+#[derive(Debug, Deserialize)]
+pub struct IndexConfig {
+    pub name: Option<String>,
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub unique: bool,
+    #[serde(rename = "where")]
+    pub condition: Option<String>,
+}
 
+#[derive(Debug, Deserialize)]
+pub struct ForeignKeyConfig {
+    pub column: String,
+    pub references: String,                // referenced table
+    pub references_column: Option<String>, // primary key of referenced table by default
+    pub on_delete: Option<String>,
+    pub on_update: Option<String>,
+}
+
+/// View is defined either with raw `sql` or with the fields of a select
+/// (`table`, `alias`, `columns`, `joins`, `where`)
+#[derive(Debug, Deserialize)]
+pub struct ViewConfig {
+    pub name: String,
+    pub sql: Option<String>,
+    pub table: Option<String>,
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub columns: Vec<String>, // all columns if empty
+    #[serde(default)]
+    pub joins: Vec<JoinConfig>,
+    #[serde(rename = "where")]
+    pub condition: Option<String>,
+    pub primary_key: Option<String>, // column identifying rows, used for pagination
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JoinConfig {
+    pub table: String,
+    pub alias: Option<String>,
+    pub on: String,
+    #[serde(default)]
+    pub kind: JoinKind,
+}
+
+impl Config {
+    // every database of the config with its tables and views, in order of definition
+    pub fn into_databases(self) -> Result<Vec<DatabaseConfig>, DataBaseError> {
+        let mut databases: Vec<DatabaseConfig> = Vec::new();
+        match self.database {
+            Some(database) if !database.include.is_empty() => {
+                return Err(DataBaseError::ConfigError(
+                    "Files are included at top level, not in [database]".to_string(),
+                ))
+            }
+            Some(mut database) => {
+                database.validate()?;
+                database.tables.extend(self.tables);
+                database.views.extend(self.views);
+                databases.push(database);
+            }
+            None if !self.tables.is_empty() || !self.views.is_empty() => {
+                return Err(DataBaseError::ConfigError(
+                    "Top level tables and views belong to [database], which is missing".to_string(),
+                ))
+            }
+            None => {}
+        }
+
+        for database in self.databases {
+            if database.name.is_none() {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Database with path \"{}\" has no name",
+                    database.path
+                )));
+            }
+            database.validate()?;
+            if databases.iter().any(|d| d.name() == database.name()) {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Database \"{}\" is defined more than once",
+                    database.name()
+                )));
+            }
+            databases.push(database);
+        }
+
+        if databases.is_empty() {
+            return Err(DataBaseError::ConfigError(
+                "Config defines neither [database] nor [[databases]]".to_string(),
+            ));
+        }
+        Ok(databases)
+    }
+}
+
+impl DatabaseConfig {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(DEFAULT_DATABASE)
+    }
+
+    // settings that cannot be used together
+    pub fn validate(&self) -> Result<(), DataBaseError> {
+        if self.audit && self.connection.read_only {
+            return Err(DataBaseError::ConfigError(format!(
+                "Database \"{}\" is read-only, changes cannot be audited",
+                self.name()
+            )));
+        }
+
+        Ok(())
+    }
+
+    // user recorded in the audit log
+    pub fn audit_user(&self) -> String {
+        self.audit_user.clone().unwrap_or_else(default_user)
+    }
+}
+
+pub fn read_config(config_path: &str) -> Result<Config, DataBaseError> {
+    let toml_content =
+        fs::read_to_string(config_path).context(|| format!("reading config {}", config_path))?;
+    toml::from_str(&toml_content).context(|| format!("in config {}", config_path))
+}
+
+// Databases of the config ready to be opened: included files are read, paths of the profile
+// (`LAB_DB_PROFILE` if not given) replace the configured ones and variables are expanded
+pub fn load_databases(
+    config_path: &str,
+    profile: Option<&str>,
+) -> Result<Vec<DatabaseConfig>, DataBaseError> {
+    let mut config = read_config(config_path)?;
+    // included files are relative to the config
+    let base_dir = Path::new(config_path).parent().unwrap_or(Path::new(""));
+
+    let included = read_included(base_dir, &config.include)?;
+    config.tables.extend(included.tables);
+    config.views.extend(included.views);
+    for database in config.databases.iter_mut() {
+        let included = read_included(base_dir, &database.include)?;
+        database.tables.extend(included.tables);
+        database.views.extend(included.views);
+    }
+
+    let profile = match profile {
+        Some(name) => Some(name.to_string()),
+        None => env::var(PROFILE_ENV).ok().filter(|name| !name.is_empty()),
+    };
+    let overrides = match &profile {
+        Some(name) => match config.profiles.remove(name) {
+            Some(overrides) => Some(overrides),
+            None => {
+                return Err(DataBaseError::ConfigError(format!(
+                    "No profile \"{}\" in config",
+                    name
+                )))
+            }
+        },
+        None => None,
+    };
+
+    let has_single = config.database.is_some();
+    let mut databases = config.into_databases()?;
+    if let Some(mut overrides) = overrides {
+        for (idx, database) in databases.iter_mut().enumerate() {
+            let path = match (idx, &overrides.path) {
+                (0, Some(path)) if has_single => Some(path.clone()),
+                _ => overrides.paths.remove(database.name()),
+            };
+            if let Some(path) = path {
+                database.path = path;
+            }
+        }
+        if let Some(name) = overrides.paths.keys().next() {
+            return Err(DataBaseError::ConfigError(format!(
+                "Profile overrides path of unknown database \"{}\"",
+                name
+            )));
+        }
+    }
+
+    for database in databases.iter_mut() {
+        database.path = expand_env(&database.path)?;
+    }
+    Ok(databases)
+}
+
+fn read_included(base_dir: &Path, patterns: &[String]) -> Result<IncludedConfig, DataBaseError> {
+    let mut included = IncludedConfig {
+        tables: Vec::new(),
+        views: Vec::new(),
+    };
+
+    for pattern in patterns {
+        let full_pattern = base_dir.join(pattern);
+        let paths = glob::glob(&full_pattern.to_string_lossy()).map_err(|e| {
+            DataBaseError::ConfigError(format!("Invalid include \"{}\": {}", pattern, e))
+        })?;
+
+        let mut found = false;
+        for path in paths {
+            let path = path.map_err(|e| DataBaseError::IoError(e.into()))?;
+            let toml_content = fs::read_to_string(&path)
+                .context(|| format!("reading included file {}", path.display()))?;
+            let file: IncludedConfig = toml::from_str(&toml_content)
+                .context(|| format!("in included file {}", path.display()))?;
+            included.tables.extend(file.tables);
+            included.views.extend(file.views);
+            found = true;
+        }
+        // a pattern may match no files, a plain path must exist
+        if !found && !pattern.contains(['*', '?', '[']) {
+            return Err(DataBaseError::ConfigError(format!(
+                "Included file \"{}\" does not exist",
+                pattern
+            )));
+        }
+    }
+
+    Ok(included)
+}
+
+// replaces `${NAME}` with the value of environment variable NAME
+fn expand_env(text: &str) -> Result<String, DataBaseError> {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(len) => start + len,
+            None => {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Unclosed \"${{\" in \"{}\"",
+                    text
+                )))
+            }
+        };
+        let name = &rest[start + 2..end];
+        match env::var(name) {
+            Ok(value) => result.push_str(&value),
+            Err(_) => {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Environment variable \"{}\" used in \"{}\" is not set",
+                    name, text
+                )))
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+// prints databases as they are opened, with included tables and paths of the profile
+pub fn print_config(config_path: &str, profile: Option<&str>) -> Result<(), DataBaseError> {
+    for database in load_databases(config_path, profile)? {
+        print_database(&database);
+    }
+
+    Ok(())
+}
+
+fn print_database(database: &DatabaseConfig) {
     // Print the configuration details
-    println!("Database Configuration:");
-    println!("  Path: {}", config.database.path);
+    println!("Database Configuration: {}", database.name());
+    println!("  Path: {}", database.path);
+    if database.audit {
+        println!("  Audit: on");
+    }
+    let options = &database.connection;
+    if let Some(mode) = options.journal_mode {
+        println!("  Journal mode: {}", mode.as_str());
+    }
+    if let Some(level) = options.synchronous {
+        println!("  Synchronous: {}", level.as_str());
+    }
+    if options.read_only {
+        println!("  Read only");
+    }
+    if database.statement_cache != DEFAULT_STATEMENT_CACHE {
+        println!("  Statement cache: {}", database.statement_cache);
+    }
 
     println!("\nTables:");
-    for (i, table) in config.tables.iter().enumerate() {
+    for (i, table) in database.tables.iter().enumerate() {
         println!("\nTable {}: {}", i + 1, table.name);
         println!("  Columns:");
         for col_info in table.columns_dtypes.iter() {
             println!("    {}: {}", col_info[0], col_info[1]);
         }
+        for column in table.columns.iter() {
+            match &column.description {
+                Some(description) => {
+                    println!("    {}: {} ({})", column.name, column.dtype, description)
+                }
+                None => println!("    {}: {}", column.name, column.dtype),
+            }
+        }
+        if !table.full_text.is_empty() {
+            println!("  Full-text search on: {}", table.full_text.join(", "));
+        }
+        for fkey in table.foreign_keys.iter() {
+            println!(
+                "  Foreign key: {} -> {}({})",
+                fkey.column,
+                fkey.references,
+                fkey.references_column.as_deref().unwrap_or("PRIMARY KEY")
+            );
+        }
+        for index in table.indexes.iter() {
+            println!(
+                "  {}Index on ({})",
+                if index.unique { "Unique " } else { "" },
+                index.columns.join(", ")
+            );
+        }
+    }
+
+    for (i, view) in database.views.iter().enumerate() {
+        println!("\nView {}: {}", i + 1, view.name);
+        match (&view.sql, &view.table) {
+            (Some(sql), _) => println!("  SQL: {}", sql),
+            (None, Some(table)) => println!("  Select from: {}", table),
+            (None, None) => println!("  No definition"),
+        }
     }
 }
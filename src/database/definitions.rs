@@ -1,27 +1,234 @@
-use rusqlite::{Connection, Params, Result};
-use toml;
+use super::audit::Audit;
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, Value, ValueRef};
 
-#[derive(Debug, Clone)]
+use rusqlite::Connection;
+use std::fmt;
+
+/// Value of a single cell. Mirrors SQLite storage classes (NULL, INTEGER, REAL, TEXT, BLOB)
+/// plus `Bit` for BOOL columns and `Param` for placeholders in generated queries.
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
-    Int(i32),
-    Float(f32),
+    Int(i64),
+    Float(f64),
     Bit(bool),
     Text(String),
+    Blob(Vec<u8>),
+    Null,
     Param(String),
 }
 
 // data types storages
 // не лучший вариант, но пусть пока будет так
-pub const SQL_STR_DTYPES: [&str; 4] = ["INTEGER", "FLOAT", "BOOL", "TEXT"];
+pub const SQL_STR_DTYPES: [&str; 6] = ["INTEGER", "FLOAT", "BOOL", "TEXT", "BLOB", "NULL"];
+
+// time of deletion of a row in tables with soft delete, NULL for live rows
+pub const DELETED_AT: &str = "deleted_at";
 
 #[derive(Clone, Debug)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<(String, String)>, // name of each column and its data type
     pub primary_key: String,
+    pub indexes: Vec<Index>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub view: Option<String>, // SELECT statement of a view, None for ordinary tables
+    pub full_text: Vec<String>, // columns indexed in FTS5 table `<name>_fts`
+    pub soft_delete: bool,    // rows are marked in hidden `deleted_at` column instead of deletion
+    pub column_docs: Vec<ColumnDoc>, // labels and descriptions of columns given in config
+}
+
+#[derive(Clone, Debug)]
+pub struct ColumnDoc {
+    pub column: String,
+    pub label: Option<String>, // shown instead of column name
+    pub description: Option<String>,
+}
+
+impl Table {
+    // plain table without indexes, keys to other tables and the rest, fields are set afterwards
+    pub fn new(name: &str, columns: Vec<(String, String)>, primary_key: &str) -> Self {
+        Table {
+            name: name.to_string(),
+            columns,
+            primary_key: primary_key.to_string(),
+            indexes: Vec::new(),
+            foreign_keys: Vec::new(),
+            view: None,
+            full_text: Vec::new(),
+            soft_delete: false,
+            column_docs: Vec::new(),
+        }
+    }
+
+    // name of the column to show to users
+    pub fn label<'a>(&'a self, column: &'a str) -> &'a str {
+        self.column_docs
+            .iter()
+            .find(|doc| doc.column == column)
+            .and_then(|doc| doc.label.as_deref())
+            .unwrap_or(column)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ForeignKey {
+    pub column: String,
+    pub ref_table: String,
+    pub ref_column: String,
+    pub on_delete: Option<String>, // CASCADE, SET NULL, RESTRICT...
+    pub on_update: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Index {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub condition: Option<String>, // WHERE part of a partial index
+}
+
+/// One page of rows walked by keyset pagination. `next` is the key of the last row,
+/// pass it to get the following page; `None` when there are no more rows.
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub rows: Vec<Vec<DataType>>,
+    pub next: Option<DataType>,
+}
+
+/// Row found by full-text search. `highlights` holds the indexed columns with matches wrapped
+/// into brackets, `rank` is bm25 score: the lower the better.
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    pub row: Vec<DataType>,
+    pub highlights: Vec<(String, String)>,
+    pub rank: f64,
 }
 
 pub struct DataBase {
     pub connection: Connection,
     pub tables: Vec<Table>,
+    pub audit: Option<Audit>, // None when changes are not recorded
+}
+
+impl From<Value> for DataType {
+    fn from(val: Value) -> Self {
+        match val {
+            Value::Null => DataType::Null,
+            Value::Integer(i) => DataType::Int(i),
+            Value::Real(f) => DataType::Float(f),
+            Value::Text(s) => DataType::Text(s),
+            Value::Blob(b) => DataType::Blob(b),
+        }
+    }
+}
+
+impl From<DataType> for Value {
+    fn from(val: DataType) -> Self {
+        match val {
+            DataType::Int(i) => Value::Integer(i),
+            DataType::Float(f) => Value::Real(f),
+            DataType::Bit(b) => Value::Integer(b as i64),
+            DataType::Text(s) => Value::Text(s),
+            DataType::Blob(b) => Value::Blob(b),
+            DataType::Null => Value::Null,
+            // placeholders carry no value of their own
+            DataType::Param(p) => Value::Text(p),
+        }
+    }
+}
+
+impl ToSql for DataType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            DataType::Int(i) => ToSqlOutput::Borrowed(ValueRef::Integer(*i)),
+            DataType::Float(f) => ToSqlOutput::Borrowed(ValueRef::Real(*f)),
+            DataType::Bit(b) => ToSqlOutput::Borrowed(ValueRef::Integer(*b as i64)),
+            DataType::Text(s) => ToSqlOutput::Borrowed(ValueRef::Text(s.as_bytes())),
+            DataType::Blob(b) => ToSqlOutput::Borrowed(ValueRef::Blob(b)),
+            DataType::Null => ToSqlOutput::Borrowed(ValueRef::Null),
+            DataType::Param(p) => {
+                return Err(rusqlite::Error::ToSqlConversionFailure(
+                    format!("placeholder \"{}\" cannot be bound as a value", p).into(),
+                ))
+            }
+        })
+    }
+}
+
+impl FromSql for DataType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Ok(Value::from(value).into())
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Int(i) => write!(f, "{}", i),
+            DataType::Float(v) => write!(f, "{}", v),
+            DataType::Bit(b) => write!(f, "{}", b),
+            DataType::Text(s) => write!(f, "{}", s),
+            DataType::Blob(_) => write!(f, "[BLOB]"),
+            DataType::Null => write!(f, "NULL"),
+            DataType::Param(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+// conversions of plain rust values, used by records mapped onto tables
+macro_rules! impl_from_int {
+    ($($t:ty),*) => {
+        $(impl From<$t> for DataType {
+            fn from(val: $t) -> Self {
+                DataType::Int(val as i64)
+            }
+        })*
+    };
+}
+
+impl_from_int!(i8, i16, i32, i64, u8, u16, u32, isize);
+
+impl From<f32> for DataType {
+    fn from(val: f32) -> Self {
+        DataType::Float(val as f64)
+    }
+}
+
+impl From<f64> for DataType {
+    fn from(val: f64) -> Self {
+        DataType::Float(val)
+    }
+}
+
+impl From<bool> for DataType {
+    fn from(val: bool) -> Self {
+        DataType::Bit(val)
+    }
+}
+
+impl From<String> for DataType {
+    fn from(val: String) -> Self {
+        DataType::Text(val)
+    }
+}
+
+impl From<&str> for DataType {
+    fn from(val: &str) -> Self {
+        DataType::Text(val.to_string())
+    }
+}
+
+impl From<Vec<u8>> for DataType {
+    fn from(val: Vec<u8>) -> Self {
+        DataType::Blob(val)
+    }
+}
+
+impl<T: Into<DataType>> From<Option<T>> for DataType {
+    fn from(val: Option<T>) -> Self {
+        match val {
+            Some(v) => v.into(),
+            None => DataType::Null,
+        }
+    }
 }
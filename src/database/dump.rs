@@ -0,0 +1,136 @@
+// Export of tables as a plain SQL script: CREATE statements built from `Table` definitions and
+// INSERT of every row ordered by primary key, so dumps of the same data are identical and can
+// be diffed. Scripts run in a single transaction and can be imported into another database.
+
+use super::audit::key_column;
+use super::definitions::{DataBase, DataType, Table, DELETED_AT};
+use super::errors::{Context, DataBaseError};
+use super::{create_table_query, get_col_names, order_by_dependencies};
+use crate::query_builder as qr;
+use crate::query_builder::ToQuery;
+
+use std::fs;
+
+impl DataBase {
+    // script with given tables and views, all of them if `names` is empty
+    pub fn dump(&self, names: &[String]) -> Result<String, DataBaseError> {
+        if let Some(name) = names
+            .iter()
+            .find(|n| !self.tables.iter().any(|t| t.name == **n))
+        {
+            return Err(DataBaseError::TableNotFound(format!(
+                "No table \"{}\" found in database ",
+                name
+            )));
+        }
+        let selected = |table: &Table| names.is_empty() || names.contains(&table.name);
+
+        let (views, tables): (Vec<Table>, Vec<Table>) =
+            self.tables.iter().cloned().partition(|t| t.view.is_some());
+
+        let mut script = String::new();
+        script.push_str("PRAGMA foreign_keys = ON;\n");
+        script.push_str("BEGIN TRANSACTION;\n");
+        // rows referring to each other in one table are checked only at the end
+        script.push_str("PRAGMA defer_foreign_keys = ON;\n");
+
+        // referenced tables go first, as in `construct_tables`
+        for table in order_by_dependencies(&tables)? {
+            if !selected(table) {
+                continue;
+            }
+
+            script.push('\n');
+            script.push_str(&create_table_query(table));
+            script.push('\n');
+            for index in &table.indexes {
+                let mut q = qr::CreateIndex::new(&index.name, &table.name).columns(&index.columns);
+                if index.unique {
+                    q = q.unique();
+                }
+                if let Some(cond) = &index.condition {
+                    q = q.condition(cond);
+                }
+                script.push_str(&q.to_query());
+                script.push('\n');
+            }
+            // created before the rows, its triggers index them while importing
+            if !table.full_text.is_empty() {
+                let q = qr::CreateFullText::new(&table.name).columns(&table.full_text);
+                script.push_str(&q.to_query());
+                script.push('\n');
+            }
+
+            for insert in self.dump_rows(table)? {
+                script.push_str(&insert);
+                script.push('\n');
+            }
+        }
+
+        for view in views.iter().filter(|v| selected(v)) {
+            script.push('\n');
+            let q =
+                qr::CreateView::new(&view.name).select(view.view.as_deref().unwrap_or_default());
+            script.push_str(&q.to_query());
+            script.push('\n');
+        }
+
+        script.push_str("\nCOMMIT;\n");
+        Ok(script)
+    }
+
+    pub fn dump_to(&self, path: &str, names: &[String]) -> Result<(), DataBaseError> {
+        fs::write(path, self.dump(names)?).context(|| format!("writing dump {}", path))?;
+        Ok(())
+    }
+
+    // runs a script made by `dump`, nothing is changed if any statement fails.
+    // Tables known to `DataBase` stay the same
+    pub fn import(&self, script: &str) -> Result<(), DataBaseError> {
+        if let Err(err) = self.connection.execute_batch(script) {
+            if !self.connection.is_autocommit() {
+                self.connection.execute_batch("ROLLBACK;")?;
+            }
+            return Err(DataBaseError::from(err));
+        }
+
+        Ok(())
+    }
+
+    pub fn import_from(&self, path: &str) -> Result<(), DataBaseError> {
+        let script = fs::read_to_string(path).context(|| format!("reading script {}", path))?;
+        self.import(&script)
+            .context(|| format!("importing {}", path))
+    }
+
+    // INSERT statements of all rows, soft deleted ones included
+    fn dump_rows(&self, table: &Table) -> Result<Vec<String>, DataBaseError> {
+        let mut columns = get_col_names(&table.columns);
+        if table.soft_delete {
+            columns.push(DELETED_AT.to_string());
+        }
+
+        let q = qr::Select::new(&table.name)
+            .columns(&columns)
+            .order_by(key_column(table), qr::Order::Asc)
+            .to_query();
+        let mut stmt = self.connection.prepare(&q).map_err(DataBaseError::from)?;
+        let mut rows = stmt.query([])?;
+
+        let mut inserts: Vec<String> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut values: Vec<DataType> = Vec::new();
+            for col_idx in 0..columns.len() {
+                values.push(row.get::<usize, DataType>(col_idx)?);
+            }
+            inserts.push(
+                qr::Insert::new(&table.name)
+                    .columns(&columns)
+                    .values(Some(values))
+                    .to_query(),
+            );
+        }
+
+        Ok(inserts)
+    }
+}
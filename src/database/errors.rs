@@ -1,6 +1,5 @@
-use csv;
-use rusqlite::{Connection, Params, Result};
-use toml;
+use std::error::Error;
+use std::fmt;
 
 ///////////// ERROR DEFINITIONS ///////////////
 #[derive(Debug)]
@@ -8,9 +7,109 @@ pub enum DataBaseError {
     CSVError(csv::Error),
     IoError(std::io::Error),
     TableNotFound(String),
+    ColumnNotFound(String),
+    ForeignKeyViolation(String),
     TomlError(toml::de::Error),
     SqliteError(rusqlite::Error),
     ConfigError(String),
+    SerdeError(String),
+    // what was being done when `source` happened: table, operation, statement...
+    Context {
+        context: String,
+        source: Box<DataBaseError>,
+    },
+}
+
+impl DataBaseError {
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        DataBaseError::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    // error without the contexts around it
+    pub fn root(&self) -> &DataBaseError {
+        match self {
+            DataBaseError::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    // message of the error followed by all its causes, e.g.
+    // `in query SELECT ...: SQLite error: no such table: T`
+    pub fn report(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = self.source();
+        while let Some(err) = source {
+            message.push_str(": ");
+            message.push_str(err.to_string().trim_end());
+            source = err.source();
+        }
+
+        message
+    }
+
+    // process exit code of the CLI for each kind of error, 2 is used by clap for wrong usage
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            DataBaseError::Context { source, .. } => source.exit_code(),
+            DataBaseError::ConfigError(_) | DataBaseError::TomlError(_) => 3,
+            DataBaseError::TableNotFound(_) | DataBaseError::ColumnNotFound(_) => 4,
+            DataBaseError::ForeignKeyViolation(_) => 5,
+            DataBaseError::SqliteError(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                5
+            }
+            DataBaseError::SqliteError(_) => 6,
+            DataBaseError::IoError(_) | DataBaseError::CSVError(_) => 7,
+            DataBaseError::SerdeError(_) => 8,
+        }
+    }
+}
+
+impl fmt::Display for DataBaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // the wrapped errors are sources, see `report`
+            DataBaseError::CSVError(_) => write!(f, "CSV error"),
+            DataBaseError::IoError(_) => write!(f, "I/O error"),
+            DataBaseError::TomlError(_) => write!(f, "Invalid TOML"),
+            DataBaseError::SqliteError(_) => write!(f, "SQLite error"),
+            DataBaseError::TableNotFound(msg)
+            | DataBaseError::ColumnNotFound(msg)
+            | DataBaseError::ForeignKeyViolation(msg)
+            | DataBaseError::ConfigError(msg)
+            | DataBaseError::SerdeError(msg) => write!(f, "{}", msg.trim_end()),
+            DataBaseError::Context { context, .. } => write!(f, "{}", context),
+        }
+    }
+}
+
+impl Error for DataBaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DataBaseError::CSVError(e) => Some(e),
+            DataBaseError::IoError(e) => Some(e),
+            DataBaseError::TomlError(e) => Some(e),
+            DataBaseError::SqliteError(e) => Some(e),
+            DataBaseError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Adds context to errors of results, e.g.
+/// `conn.prepare(&q).context(|| format!("in query {}", q))?`
+pub trait Context<T> {
+    fn context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T, DataBaseError>;
+}
+
+impl<T, E: Into<DataBaseError>> Context<T> for Result<T, E> {
+    fn context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T, DataBaseError> {
+        self.map_err(|err| err.into().context(context()))
+    }
 }
 
 impl From<String> for DataBaseError {
@@ -42,3 +141,26 @@ impl From<csv::Error> for DataBaseError {
         DataBaseError::CSVError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_are_chained() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        let err = conn
+            .execute_batch("SELECT * FROM Missing;")
+            .context(|| "in query SELECT * FROM Missing")
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "in query SELECT * FROM Missing");
+        let inner = err.source().unwrap();
+        assert_eq!(inner.to_string(), "SQLite error");
+        assert!(inner.source().unwrap().is::<rusqlite::Error>());
+        assert!(err
+            .report()
+            .starts_with("in query SELECT * FROM Missing: SQLite error: no such table: Missing"));
+        assert_eq!(err.exit_code(), 6);
+    }
+}
@@ -0,0 +1,709 @@
+// Check of a config that reports every problem found instead of stopping at the first one.
+// Each problem points to its place in the file as `file:line:column: message`, so it can be
+// jumped to from an editor. Included files are checked with their own places.
+
+use super::configuration::*;
+use super::{column_problems, parse_tables_configs, unknown_columns, validate_identifier};
+
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use toml_edit::{ImDocument, Item};
+
+// type names of sqlite docs and their common spellings, other names get an affinity as well,
+// but are more likely typos. The first word is compared, e.g. UNSIGNED of UNSIGNED BIG INT
+const KNOWN_TYPES: [&str; 30] = [
+    "INT",
+    "INTEGER",
+    "TINYINT",
+    "SMALLINT",
+    "MEDIUMINT",
+    "BIGINT",
+    "UNSIGNED",
+    "INT2",
+    "INT8",
+    "CHARACTER",
+    "VARCHAR",
+    "VARYING",
+    "NCHAR",
+    "NATIVE",
+    "NVARCHAR",
+    "TEXT",
+    "CLOB",
+    "BLOB",
+    "REAL",
+    "DOUBLE",
+    "FLOAT",
+    "NUMERIC",
+    "DECIMAL",
+    "BOOL",
+    "BOOLEAN",
+    "DATE",
+    "DATETIME",
+    "TIME",
+    "TIMESTAMP",
+    "NULL",
+];
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize, // 0 when the place in the file is unknown
+    pub column: usize,
+    pub message: String,
+    pub warning: bool, // does not make the config fail
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.warning { "warning: " } else { "" };
+        if self.line == 0 {
+            write!(f, "{}: {}{}", self.file, kind, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}{}",
+                self.file, self.line, self.column, kind, self.message
+            )
+        }
+    }
+}
+
+// all problems of the config and the files it includes, empty if there are none
+pub fn check_config(config_path: &str) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        sources: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let config: Config = match checker.load(Path::new(config_path)) {
+        Some((_, config)) => config,
+        None => return checker.diagnostics,
+    };
+    let root = Place {
+        source: 0,
+        path: Vec::new(),
+    };
+    let base_dir = Path::new(config_path).parent().unwrap_or(Path::new(""));
+
+    let mut databases: Vec<Entries> = Vec::new();
+    match config.database {
+        Some(database) => {
+            let place = root.key("database");
+            if let Err(err) = database.validate() {
+                checker.report(&place.key("audit"), err.report());
+            }
+            if !database.include.is_empty() {
+                checker.report(
+                    &place.key("include"),
+                    "Files are included at top level, not in [database]".to_string(),
+                );
+            }
+            let mut entries = Entries::new(DEFAULT_DATABASE, place.clone());
+            entries.add(&place, database.tables, database.views);
+            entries.add(&root, config.tables, config.views);
+            checker.include(base_dir, &root, &config.include, &mut entries);
+            databases.push(entries);
+        }
+        None if !config.tables.is_empty() || !config.views.is_empty() => checker.report(
+            &root.key(if config.tables.is_empty() {
+                "views"
+            } else {
+                "tables"
+            }),
+            "Top level tables and views belong to [database], which is missing".to_string(),
+        ),
+        None => {}
+    }
+
+    for (idx, database) in config.databases.into_iter().enumerate() {
+        let place = root.key("databases").index(idx);
+        let name = match &database.name {
+            Some(name) => name.clone(),
+            None => {
+                checker.report(
+                    &place,
+                    format!("Database with path \"{}\" has no name", database.path),
+                );
+                continue;
+            }
+        };
+        if databases.iter().any(|d| d.name == name) {
+            checker.report(
+                &place.key("name"),
+                format!("Database \"{}\" is defined more than once", name),
+            );
+            continue;
+        }
+
+        if let Err(err) = database.validate() {
+            checker.report(&place.key("audit"), err.report());
+        }
+
+        let mut entries = Entries::new(&name, place.clone());
+        entries.add(&place, database.tables, database.views);
+        checker.include(base_dir, &place, &database.include, &mut entries);
+        databases.push(entries);
+    }
+    if databases.is_empty() && checker.diagnostics.is_empty() {
+        checker.report(
+            &root,
+            "Config defines neither [database] nor [[databases]]".to_string(),
+        );
+    }
+
+    let names: Vec<&str> = databases.iter().map(|d| d.name.as_str()).collect();
+    let mut profiles: Vec<(&String, &ProfileConfig)> = config.profiles.iter().collect();
+    profiles.sort_by_key(|(name, _)| *name);
+    for (name, profile) in profiles {
+        let place = root.key("profiles").key(name);
+        if profile.path.is_some() && !names.contains(&DEFAULT_DATABASE) {
+            checker.report(
+                &place.key("path"),
+                format!("Profile \"{}\" overrides path of missing [database]", name),
+            );
+        }
+        let mut paths: Vec<&String> = profile.paths.keys().collect();
+        paths.sort();
+        for database in paths {
+            if !names.contains(&database.as_str()) {
+                checker.report(
+                    &place.key("paths").key(database),
+                    format!(
+                        "Profile \"{}\" overrides path of unknown database \"{}\"",
+                        name, database
+                    ),
+                );
+            }
+        }
+    }
+
+    for database in databases {
+        checker.check_database(database);
+    }
+
+    // in order of appearance in each file
+    let mut diagnostics = checker.diagnostics;
+    diagnostics.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+    diagnostics
+}
+
+// a file of the config with positions of its values
+struct Source {
+    file: String,
+    text: String,
+    document: ImDocument<String>,
+}
+
+#[derive(Clone)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+// value in one of the sources, given by keys and indexes leading to it
+#[derive(Clone)]
+struct Place {
+    source: usize,
+    path: Vec<Step>,
+}
+
+impl Place {
+    fn key(&self, key: &str) -> Place {
+        let mut place = self.clone();
+        place.path.push(Step::Key(key.to_string()));
+        place
+    }
+
+    fn index(&self, idx: usize) -> Place {
+        let mut place = self.clone();
+        place.path.push(Step::Index(idx));
+        place
+    }
+}
+
+// tables and views of one database with their places
+struct Entries {
+    name: String,
+    place: Place,
+    tables: Vec<(Place, TableConfig)>,
+    views: Vec<(Place, ViewConfig)>,
+}
+
+impl Entries {
+    fn new(name: &str, place: Place) -> Self {
+        Entries {
+            name: name.to_string(),
+            place,
+            tables: Vec::new(),
+            views: Vec::new(),
+        }
+    }
+
+    // `parent` holds the `tables` and `views` arrays
+    fn add(&mut self, parent: &Place, tables: Vec<TableConfig>, views: Vec<ViewConfig>) {
+        for (idx, table) in tables.into_iter().enumerate() {
+            self.tables.push((parent.key("tables").index(idx), table));
+        }
+        for (idx, view) in views.into_iter().enumerate() {
+            self.views.push((parent.key("views").index(idx), view));
+        }
+    }
+}
+
+// columns of a configured table
+struct TableColumns {
+    name: String,
+    columns: Vec<String>,
+    has_primary_key: bool,
+}
+
+struct Checker {
+    sources: Vec<Source>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    // reads and parses a file, problems are reported and give None
+    fn load<T: DeserializeOwned>(&mut self, path: &Path) -> Option<(usize, T)> {
+        let file = path.display().to_string();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                self.report_at(&file, None, None, err.to_string());
+                return None;
+            }
+        };
+        let document = match ImDocument::parse(text.clone()) {
+            Ok(document) => document,
+            Err(err) => {
+                self.report_at(&file, Some(&text), err.span(), err.message().to_string());
+                return None;
+            }
+        };
+        let value: T = match toml::from_str(&text) {
+            Ok(value) => value,
+            Err(err) => {
+                self.report_at(&file, Some(&text), err.span(), err.message().to_string());
+                return None;
+            }
+        };
+
+        self.sources.push(Source {
+            file,
+            text,
+            document,
+        });
+        Some((self.sources.len() - 1, value))
+    }
+
+    // adds tables and views of the files matching `patterns` given at `place`
+    fn include(&mut self, base_dir: &Path, place: &Place, patterns: &[String], into: &mut Entries) {
+        for (idx, pattern) in patterns.iter().enumerate() {
+            let pattern_place = place.key("include").index(idx);
+            let full_pattern = base_dir.join(pattern);
+            let paths = match glob::glob(&full_pattern.to_string_lossy()) {
+                Ok(paths) => paths,
+                Err(err) => {
+                    self.report(
+                        &pattern_place,
+                        format!("Invalid include \"{}\": {}", pattern, err),
+                    );
+                    continue;
+                }
+            };
+
+            let mut found = false;
+            for path in paths {
+                found = true;
+                let path = match path {
+                    Ok(path) => path,
+                    Err(err) => {
+                        self.report(&pattern_place, err.to_string());
+                        continue;
+                    }
+                };
+                if let Some((source, file)) = self.load::<IncludedConfig>(&path) {
+                    let root = Place {
+                        source,
+                        path: Vec::new(),
+                    };
+                    into.add(&root, file.tables, file.views);
+                }
+            }
+            if !found && !pattern.contains(['*', '?', '[']) {
+                self.report(
+                    &pattern_place,
+                    format!("Included file \"{}\" does not exist", pattern),
+                );
+            }
+        }
+    }
+
+    fn check_database(&mut self, database: Entries) {
+        let reported = self.errors();
+        let mut names: HashSet<String> = HashSet::new();
+
+        let mut tables: Vec<TableColumns> = Vec::new();
+        for (place, table) in &database.tables {
+            self.check_name("table", &place.key("name"), &table.name, &mut names);
+            tables.push(self.check_columns(place, table));
+        }
+        for ((place, table), columns) in database.tables.iter().zip(&tables) {
+            self.check_references(place, table, columns, &tables);
+        }
+
+        for (place, view) in &database.views {
+            self.check_name("view", &place.key("name"), &view.name, &mut names);
+            match (&view.sql, &view.table) {
+                (Some(_), None) => {}
+                (None, Some(table)) => {
+                    if !database.tables.iter().any(|(_, t)| t.name == *table)
+                        && !database.views.iter().any(|(_, v)| v.name == *table)
+                    {
+                        self.report(
+                            &place.key("table"),
+                            format!(
+                                "View \"{}\" selects from missing table \"{}\"",
+                                view.name, table
+                            ),
+                        );
+                    }
+                }
+                _ => self.report(
+                    place,
+                    format!(
+                        "View \"{}\" must be defined either with \"sql\" or with \"table\"",
+                        view.name
+                    ),
+                ),
+            }
+            for (idx, join) in view.joins.iter().enumerate() {
+                if !database.tables.iter().any(|(_, t)| t.name == join.table)
+                    && !database.views.iter().any(|(_, v)| v.name == join.table)
+                {
+                    self.report(
+                        &place.key("joins").index(idx).key("table"),
+                        format!(
+                            "View \"{}\" joins missing table \"{}\"",
+                            view.name, join.table
+                        ),
+                    );
+                }
+            }
+        }
+
+        // what is left is known only to sqlite, e.g. mistakes in queries of views
+        if self.errors() == reported {
+            let (tables, views): (Vec<TableConfig>, Vec<ViewConfig>) = (
+                database.tables.into_iter().map(|(_, t)| t).collect(),
+                database.views.into_iter().map(|(_, v)| v).collect(),
+            );
+            if let Err(err) = parse_tables_configs(&tables, &views) {
+                self.report(&database.place, err.report());
+            }
+        }
+    }
+
+    fn check_name(&mut self, kind: &str, place: &Place, name: &str, names: &mut HashSet<String>) {
+        if let Err(err) = validate_identifier(kind, name) {
+            self.report(place, err.report());
+        }
+        if !names.insert(name.to_string()) {
+            self.report(
+                place,
+                format!("Name \"{}\" is used by another table or view", name),
+            );
+        }
+    }
+
+    // names, types and primary keys of columns, then their uses in the table
+    fn check_columns(&mut self, place: &Place, table: &TableConfig) -> TableColumns {
+        // place, name, type with constraints and whether it is the primary key
+        let mut definitions: Vec<(Place, &str, &str, bool)> = Vec::new();
+        match (table.columns_dtypes.is_empty(), table.columns.is_empty()) {
+            (true, true) => self.report(place, format!("Table \"{}\" has no columns", table.name)),
+            (false, false) => self.report(
+                place,
+                format!(
+                    "Columns of table \"{}\" are given both in \"columns_dtypes\" and \"columns\"",
+                    table.name
+                ),
+            ),
+            _ => {}
+        }
+        for (idx, col_def) in table.columns_dtypes.iter().enumerate() {
+            let col_place = place.key("columns_dtypes").index(idx);
+            match col_def.as_slice() {
+                [name, dtype] => {
+                    let primary = dtype.to_uppercase().contains("PRIMARY KEY");
+                    definitions.push((col_place, name, dtype, primary));
+                }
+                _ => self.report(
+                    &col_place,
+                    format!(
+                        "Column of table \"{}\" must be [name, type], got {} values",
+                        table.name,
+                        col_def.len()
+                    ),
+                ),
+            }
+        }
+        for (idx, column) in table.columns.iter().enumerate() {
+            let col_place = place.key("columns").index(idx);
+            definitions.push((col_place, &column.name, &column.dtype, column.primary_key));
+        }
+
+        let mut columns: Vec<String> = Vec::new();
+        let mut has_primary_key = false;
+        for (col_place, name, dtype, primary) in definitions {
+            for err in column_problems(table, name, dtype) {
+                self.report(&col_place, err.report());
+            }
+            if columns.iter().any(|c| c == name) {
+                self.report(
+                    &col_place,
+                    format!(
+                        "Column \"{}\" of table \"{}\" is defined more than once",
+                        name, table.name
+                    ),
+                );
+            }
+            // the first word of the type without its size, e.g. TEXT of `text(20) NOT NULL`
+            let base_type = dtype
+                .split(|c: char| c.is_whitespace() || c == '(')
+                .find(|word| !word.is_empty())
+                .unwrap_or_default()
+                .to_uppercase();
+            if !base_type.is_empty() && !KNOWN_TYPES.contains(&base_type.as_str()) {
+                self.warn(
+                    &col_place,
+                    format!(
+                        "Unknown type \"{}\" of column \"{}\", sqlite reads it as {}",
+                        base_type,
+                        name,
+                        affinity(&base_type)
+                    ),
+                );
+            }
+            if primary {
+                if has_primary_key {
+                    self.report(
+                        &col_place,
+                        format!("Table \"{}\" has more than one primary key", table.name),
+                    );
+                }
+                has_primary_key = true;
+            }
+            columns.push(name.to_string());
+        }
+
+        for (idx, err) in
+            unknown_columns(&table.name, "Full-text index", &table.full_text, &columns)
+        {
+            self.report(&place.key("full_text").index(idx), err.report());
+        }
+        for (idx, index) in table.indexes.iter().enumerate() {
+            let index_place = place.key("indexes").index(idx);
+            if index.columns.is_empty() {
+                self.report(
+                    &index_place,
+                    format!("Index of table \"{}\" has no columns", table.name),
+                );
+            }
+            for (col_idx, err) in unknown_columns(&table.name, "Index", &index.columns, &columns) {
+                self.report(&index_place.key("columns").index(col_idx), err.report());
+            }
+        }
+
+        TableColumns {
+            name: table.name.clone(),
+            columns,
+            has_primary_key,
+        }
+    }
+
+    fn check_references(
+        &mut self,
+        place: &Place,
+        table: &TableConfig,
+        columns: &TableColumns,
+        tables: &[TableColumns],
+    ) {
+        for (idx, fkey) in table.foreign_keys.iter().enumerate() {
+            let fkey_place = place.key("foreign_keys").index(idx);
+            if !columns.columns.contains(&fkey.column) {
+                self.report(
+                    &fkey_place.key("column"),
+                    format!(
+                        "Foreign key of table \"{}\" uses unknown column \"{}\"",
+                        table.name, fkey.column
+                    ),
+                );
+            }
+
+            let referenced = match tables.iter().find(|t| t.name == fkey.references) {
+                Some(referenced) => referenced,
+                None => {
+                    self.report(
+                        &fkey_place.key("references"),
+                        format!(
+                            "Foreign key of table \"{}\" references missing table \"{}\"",
+                            table.name, fkey.references
+                        ),
+                    );
+                    continue;
+                }
+            };
+            match &fkey.references_column {
+                Some(col) if !referenced.columns.contains(col) => self.report(
+                    &fkey_place.key("references_column"),
+                    format!(
+                        "Foreign key of table \"{}\" references unknown column \"{}\" of \"{}\"",
+                        table.name, col, referenced.name
+                    ),
+                ),
+                None if !referenced.has_primary_key => self.report(
+                    &fkey_place.key("references"),
+                    format!(
+                        "Table \"{}\" referenced by \"{}\" has no primary key, give \"references_column\"",
+                        referenced.name, table.name
+                    ),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn report(&mut self, place: &Place, message: String) {
+        let source = &self.sources[place.source];
+        let span = locate(&source.document, &place.path);
+        let diagnostic = diagnostic(&source.file, Some(&source.text), span, message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn errors(&self) -> usize {
+        self.diagnostics.iter().filter(|d| !d.warning).count()
+    }
+
+    fn warn(&mut self, place: &Place, message: String) {
+        self.report(place, message);
+        if let Some(diagnostic) = self.diagnostics.last_mut() {
+            diagnostic.warning = true;
+        }
+    }
+
+    fn report_at(
+        &mut self,
+        file: &str,
+        text: Option<&str>,
+        span: Option<Range<usize>>,
+        message: String,
+    ) {
+        self.diagnostics.push(diagnostic(file, text, span, message));
+    }
+}
+
+// span of the value at `path`, or of the closest enclosing value that has one
+fn locate(document: &ImDocument<String>, path: &[Step]) -> Option<Range<usize>> {
+    let mut item: &Item = document.as_item();
+    let mut span = None;
+    for step in path {
+        let next = match step {
+            Step::Key(key) => item.get(key.as_str()),
+            Step::Index(idx) => item.get(*idx),
+        };
+        item = match next {
+            Some(next) => next,
+            None => break,
+        };
+        span = item.span().or(span);
+    }
+
+    span
+}
+
+fn diagnostic(
+    file: &str,
+    text: Option<&str>,
+    span: Option<Range<usize>>,
+    message: String,
+) -> Diagnostic {
+    let (line, column) = match (text, span) {
+        (Some(text), Some(span)) => {
+            let before = &text[..span.start.min(text.len())];
+            let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+            (
+                before.matches('\n').count() + 1,
+                before[line_start..].chars().count() + 1,
+            )
+        }
+        _ => (0, 0),
+    };
+
+    Diagnostic {
+        file: file.to_string(),
+        line,
+        column,
+        message,
+        warning: false,
+    }
+}
+
+// affinity sqlite gives to a column by its type name
+fn affinity(type_name: &str) -> &'static str {
+    let name = type_name.to_uppercase();
+    if name.contains("INT") {
+        "INTEGER"
+    } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| name.contains(t)) {
+        "TEXT"
+    } else if name.contains("BLOB") {
+        "BLOB"
+    } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| name.contains(t)) {
+        "REAL"
+    } else {
+        "NUMERIC"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_types_are_warnings() {
+        let path = std::env::temp_dir().join(format!("databaser_lint_{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+[database]
+path = ":memory:"
+
+[[tables]]
+name = "T"
+columns_dtypes = [["id", "INT PRIMARY KEY"], ["a", "VARCHAR(20)"], ["b", "INTEGR"], ["c", ""]]
+"#,
+        )
+        .unwrap();
+        let diagnostics = check_config(&path.to_string_lossy());
+        fs::remove_file(&path).unwrap();
+
+        let messages: Vec<(bool, &str, usize)> = diagnostics
+            .iter()
+            .map(|d| (d.warning, d.message.as_str(), d.line))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    true,
+                    "Unknown type \"INTEGR\" of column \"b\", sqlite reads it as INTEGER",
+                    7
+                ),
+                (false, "Column \"c\" of table \"T\" has no type", 7),
+            ]
+        );
+    }
+}
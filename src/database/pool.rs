@@ -0,0 +1,248 @@
+// Connections to one database for use from several threads. Tables are parsed once and every
+// connection gets a copy of them. The database is switched to WAL, so readers do not wait for
+// the writer: there is a single writer behind a mutex and up to `max_readers` read-only
+// connections, opened when needed and reused afterwards.
+
+use super::configuration::{ConnectionOptions, DatabaseConfig, JournalMode, MEMORY_PATH};
+use super::definitions::{DataBase, Table};
+use super::errors::{Context, DataBaseError};
+use super::parse_tables_configs;
+
+use std::ops::Deref;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+pub struct DataBasePool {
+    config: DatabaseConfig,
+    tables: Vec<Table>,
+    max_readers: usize,
+    writer: Mutex<DataBase>,
+    readers: Mutex<Readers>,
+    released: Condvar, // signalled when a reader is back in the pool
+}
+
+struct Readers {
+    idle: Vec<DataBase>,
+    opened: usize,
+}
+
+/// Read-only connection taken from the pool, it goes back when dropped
+pub struct PooledReader<'a> {
+    pool: &'a DataBasePool,
+    db: Option<DataBase>,
+}
+
+impl DataBasePool {
+    pub fn new(config: DatabaseConfig, max_readers: usize) -> Result<Self, DataBaseError> {
+        if config.path == MEMORY_PATH {
+            return Err(DataBaseError::ConfigError(
+                "Database in memory cannot be shared by a pool, give it a file".to_string(),
+            ));
+        }
+        if max_readers == 0 {
+            return Err(DataBaseError::ConfigError(
+                "Pool needs at least one reader".to_string(),
+            ));
+        }
+        let tables = parse_tables_configs(&config.tables, &config.views)
+            .context(|| format!("in tables of database \"{}\"", config.name()))?;
+
+        let options = ConnectionOptions {
+            journal_mode: Some(JournalMode::Wal),
+            read_only: false,
+            ..config.connection.clone()
+        };
+        let mut writer = DataBase::open(&config, &options, tables.clone())?;
+        if config.audit {
+            writer.enable_audit(&config.audit_user())?;
+        }
+
+        Ok(DataBasePool {
+            config,
+            tables,
+            max_readers,
+            writer: Mutex::new(writer),
+            readers: Mutex::new(Readers {
+                idle: Vec::new(),
+                opened: 0,
+            }),
+            released: Condvar::new(),
+        })
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    // the only connection that changes the database, other threads wait for it
+    pub fn writer(&self) -> MutexGuard<'_, DataBase> {
+        // connection is still usable after a panic in another thread
+        self.writer.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // waits for a free reader if all `max_readers` are in use
+    pub fn reader(&self) -> Result<PooledReader<'_>, DataBaseError> {
+        let mut readers = self.readers.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            if let Some(db) = readers.idle.pop() {
+                return Ok(PooledReader {
+                    pool: self,
+                    db: Some(db),
+                });
+            }
+            if readers.opened < self.max_readers {
+                readers.opened += 1;
+                break;
+            }
+            readers = self
+                .released
+                .wait(readers)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+        // opened without the lock, other threads may take readers meanwhile
+        drop(readers);
+
+        match self.open_reader() {
+            Ok(db) => Ok(PooledReader {
+                pool: self,
+                db: Some(db),
+            }),
+            Err(err) => {
+                let mut readers = self.readers.lock().unwrap_or_else(|err| err.into_inner());
+                readers.opened -= 1;
+                self.released.notify_one();
+                Err(err)
+            }
+        }
+    }
+
+    fn open_reader(&self) -> Result<DataBase, DataBaseError> {
+        let options = ConnectionOptions {
+            journal_mode: None, // WAL is kept in the file, set by the writer
+            ..self.config.connection.clone()
+        };
+        let db = DataBase::open(&self.config, &options, self.tables.clone())?;
+        db.connection.pragma_update(None, "query_only", "ON")?;
+
+        Ok(db)
+    }
+
+    fn release(&self, db: DataBase) {
+        let mut readers = self.readers.lock().unwrap_or_else(|err| err.into_inner());
+        readers.idle.push(db);
+        self.released.notify_one();
+    }
+}
+
+impl Deref for PooledReader<'_> {
+    type Target = DataBase;
+
+    fn deref(&self) -> &DataBase {
+        self.db.as_ref().expect("reader is taken only on drop")
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            self.pool.release(db);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::definitions::DataType;
+    use crate::query_builder as qr;
+
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    // pool over a fresh file with table `T (id, name)`, removed when dropped
+    struct TestPool {
+        pool: DataBasePool,
+        path: String,
+    }
+
+    impl TestPool {
+        fn new(name: &str, max_readers: usize) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("databaser_pool_{}_{}.db", name, std::process::id()))
+                .to_string_lossy()
+                .to_string();
+            let config: DatabaseConfig = toml::from_str(&format!(
+                r#"
+path = "{}"
+[[tables]]
+name = "T"
+columns_dtypes = [["id", "INTEGER PRIMARY KEY"], ["name", "TEXT"]]
+"#,
+                path
+            ))
+            .unwrap();
+            let pool = DataBasePool::new(config, max_readers).unwrap();
+            pool.writer().construct_tables().unwrap();
+
+            TestPool { pool, path }
+        }
+    }
+
+    impl Drop for TestPool {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", self.path, suffix));
+            }
+        }
+    }
+
+    #[test]
+    fn reader_waits_when_all_are_taken() {
+        let test = TestPool::new("wait", 2);
+        let pool = &test.pool;
+        let first = pool.reader().unwrap();
+        let second = pool.reader().unwrap();
+
+        thread::scope(|scope| {
+            let (sender, received) = mpsc::channel();
+            scope.spawn(move || {
+                let reader = pool.reader().unwrap();
+                sender.send(()).unwrap();
+                drop(reader);
+            });
+
+            assert!(received.recv_timeout(Duration::from_millis(200)).is_err());
+            drop(first);
+            assert!(received.recv_timeout(Duration::from_secs(5)).is_ok());
+        });
+
+        // readers are reused, no more than `max_readers` are opened
+        drop(second);
+        assert_eq!(pool.readers.lock().unwrap().opened, 2);
+    }
+
+    #[test]
+    fn writes_are_seen_by_readers() {
+        let test = TestPool::new("wal", 1);
+        let pool = &test.pool;
+        let mode: String = pool
+            .writer()
+            .connection
+            .query_row("PRAGMA journal_mode;", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        let reader = pool.reader().unwrap();
+        assert!(reader.select(&qr::Select::new("T")).unwrap().is_empty());
+        pool.writer()
+            .insert_values("T", &[("name".to_string(), DataType::from("scale"))])
+            .unwrap();
+
+        let rows = reader.select(&qr::Select::new("T")).unwrap();
+        assert_eq!(rows, vec![vec![DataType::Int(1), DataType::from("scale")]]);
+        // readers cannot change the database
+        assert!(reader
+            .insert_values("T", &[("name".to_string(), DataType::from("oven"))])
+            .is_err());
+    }
+}
@@ -0,0 +1,25 @@
+use super::definitions::{DataType, Table};
+use crate::query_builder as qr;
+
+use rusqlite::Row;
+
+/// Rust struct stored as a row of a table. Usually implemented with `#[derive(DbRecord)]`.
+pub trait DbRecord: Sized {
+    /// Definition of the table the struct is mapped onto
+    fn table() -> Table;
+
+    /// Values of the fields in the order of `table().columns`
+    fn to_values(&self) -> Vec<DataType>;
+
+    /// Builds the struct from a row selected by column names
+    fn from_row(row: &Row) -> rusqlite::Result<Self>;
+
+    fn to_insert(&self) -> qr::Insert {
+        let table = Self::table();
+        let columns: Vec<String> = table.columns.iter().map(|c| c.0.clone()).collect();
+
+        qr::Insert::new(&table.name)
+            .columns(&columns)
+            .values(Some(self.to_values()))
+    }
+}
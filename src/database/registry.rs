@@ -0,0 +1,64 @@
+// All databases of a config by name. A database is opened on first access, so a config
+// listing many databases does not open files that are not used.
+
+use super::configuration::{load_databases, DatabaseConfig};
+use super::definitions::DataBase;
+use super::errors::DataBaseError;
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+pub struct DataBaseRegistry {
+    configs: Vec<DatabaseConfig>,
+    opened: HashMap<String, DataBase>,
+}
+
+impl DataBaseRegistry {
+    // `profile` overrides paths of databases, see `load_databases`
+    pub fn from_config(config_path: &str, profile: Option<&str>) -> Result<Self, DataBaseError> {
+        Ok(DataBaseRegistry {
+            configs: load_databases(config_path, profile)?,
+            opened: HashMap::new(),
+        })
+    }
+
+    // names in order of definition
+    pub fn names(&self) -> Vec<&str> {
+        self.configs.iter().map(|c| c.name()).collect()
+    }
+
+    // the first database of the config
+    pub fn default_name(&self) -> &str {
+        self.configs[0].name()
+    }
+
+    pub fn is_open(&self, name: &str) -> bool {
+        self.opened.contains_key(name)
+    }
+
+    pub fn get(&mut self, name: &str) -> Result<&DataBase, DataBaseError> {
+        self.get_mut(name).map(|db| &*db)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut DataBase, DataBaseError> {
+        match self.opened.entry(name.to_string()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => match self.configs.iter().find(|c| c.name() == name) {
+                Some(config) => Ok(entry.insert(DataBase::from_database_config(config)?)),
+                None => {
+                    let known: Vec<&str> = self.configs.iter().map(|c| c.name()).collect();
+                    Err(DataBaseError::ConfigError(format!(
+                        "No database \"{}\" in config, known are: {}",
+                        name,
+                        known.join(", ")
+                    )))
+                }
+            },
+        }
+    }
+
+    // closes the database, it is opened again on next access
+    pub fn close(&mut self, name: &str) -> bool {
+        self.opened.remove(name).is_some()
+    }
+}
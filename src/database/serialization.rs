@@ -0,0 +1,268 @@
+// Conversion between table rows and serde types. Rows are passed through `toml::Value`, which
+// already implements both serde data model directions, so no hand-written (de)serializer is needed.
+// TOML has no NULL, thus NULL cells are omitted and map onto `Option` fields as `None`.
+// SQLite keeps booleans as integers, cells are wrapped to read 0 and 1 into `bool` fields.
+
+use super::definitions::DataType;
+use super::errors::DataBaseError;
+
+use rusqlite::Row;
+use serde::de::value::MapDeserializer;
+use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::Serialize;
+use serde_json::{Number, Value as JsonValue};
+use toml::{Table as TomlTable, Value as TomlValue};
+
+pub fn row_to_struct<T: DeserializeOwned>(row: &Row) -> Result<T, DataBaseError> {
+    let mut fields = TomlTable::new();
+
+    for (idx, name) in row.as_ref().column_names().iter().enumerate() {
+        let val: DataType = row.get(idx)?;
+        let toml_val = match val {
+            DataType::Int(i) => TomlValue::Integer(i),
+            DataType::Float(f) => TomlValue::Float(f),
+            DataType::Bit(b) => TomlValue::Boolean(b),
+            DataType::Text(s) | DataType::Param(s) => TomlValue::String(s),
+            DataType::Blob(b) => TomlValue::Array(
+                b.into_iter()
+                    .map(|x| TomlValue::Integer(x as i64))
+                    .collect(),
+            ),
+            DataType::Null => continue,
+        };
+        fields.insert(name.to_string(), toml_val);
+    }
+
+    let cells = fields.into_iter().map(|(name, val)| (name, Cell(val)));
+    T::deserialize(MapDeserializer::new(cells)).map_err(|e: toml::de::Error| {
+        DataBaseError::SerdeError(format!("Could not deserialize row: {}", e.message()))
+    })
+}
+
+// value of a row cell, forwards to `toml::Value` except for booleans stored as integers
+struct Cell(TomlValue);
+
+impl<'de> IntoDeserializer<'de, toml::de::Error> for Cell {
+    type Deserializer = Cell;
+
+    fn into_deserializer(self) -> Cell {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Cell {
+    type Error = toml::de::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_any(visitor)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            TomlValue::Integer(i @ (0 | 1)) => visitor.visit_bool(i == 1),
+            val => val.deserialize_bool(visitor),
+        }
+    }
+
+    // NULL cells are omitted, a present cell is always `Some`
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+// returns names of serialized fields and their values
+pub fn struct_to_values<T: Serialize>(
+    record: &T,
+) -> Result<(Vec<String>, Vec<DataType>), DataBaseError> {
+    let fields = match TomlValue::try_from(record) {
+        Ok(TomlValue::Table(fields)) => fields,
+        Ok(_) => {
+            return Err(DataBaseError::SerdeError(
+                "Only structs and maps can be inserted as rows".to_string(),
+            ))
+        }
+        Err(e) => {
+            return Err(DataBaseError::SerdeError(format!(
+                "Could not serialize record: {}",
+                e
+            )))
+        }
+    };
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut vals: Vec<DataType> = Vec::new();
+    for (name, val) in fields {
+        vals.push(toml_to_datatype(&name, val)?);
+        columns.push(name);
+    }
+
+    Ok((columns, vals))
+}
+
+fn toml_to_datatype(name: &str, val: TomlValue) -> Result<DataType, DataBaseError> {
+    Ok(match val {
+        TomlValue::Integer(i) => DataType::Int(i),
+        TomlValue::Float(f) => DataType::Float(f),
+        TomlValue::Boolean(b) => DataType::Bit(b),
+        TomlValue::String(s) => DataType::Text(s),
+        TomlValue::Datetime(d) => DataType::Text(d.to_string()),
+        TomlValue::Array(arr) => {
+            // sequences of bytes are stored as BLOB
+            let mut bytes: Vec<u8> = Vec::new();
+            for item in arr {
+                match item.as_integer().map(u8::try_from) {
+                    Some(Ok(b)) => bytes.push(b),
+                    _ => {
+                        return Err(DataBaseError::SerdeError(format!(
+                            "Field \"{}\": only byte sequences can be stored in a column",
+                            name
+                        )))
+                    }
+                }
+            }
+            DataType::Blob(bytes)
+        }
+        TomlValue::Table(_) => {
+            return Err(DataBaseError::SerdeError(format!(
+                "Field \"{}\": nested structures cannot be stored in a column",
+                name
+            )))
+        }
+    })
+}
+
+// value of a cell as JSON, bytes of BLOB as an array of numbers
+pub fn to_json(val: DataType) -> JsonValue {
+    match val {
+        DataType::Int(i) => JsonValue::from(i),
+        DataType::Float(f) => Number::from_f64(f).map_or(JsonValue::Null, JsonValue::Number),
+        DataType::Bit(b) => JsonValue::Bool(b),
+        DataType::Text(s) | DataType::Param(s) => JsonValue::String(s),
+        DataType::Blob(b) => JsonValue::from(b),
+        DataType::Null => JsonValue::Null,
+    }
+}
+
+// JSON value checked against the declared type of the column (`dtype` with its constraints),
+// e.g. an INTEGER column takes only integers and a NOT NULL column does not take null
+pub fn json_to_datatype(
+    column: &str,
+    dtype: &str,
+    val: &JsonValue,
+) -> Result<DataType, DataBaseError> {
+    let definition = dtype.to_uppercase();
+    let base_type = definition
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .find(|word| !word.is_empty())
+        .unwrap_or_default();
+
+    let converted = match (base_type, val) {
+        (_, JsonValue::Null) if definition.contains("NOT NULL") => None,
+        (_, JsonValue::Null) => Some(DataType::Null),
+        ("INTEGER" | "INT", JsonValue::Number(n)) => n.as_i64().map(DataType::Int),
+        ("FLOAT" | "REAL" | "NUMERIC", JsonValue::Number(n)) => n.as_f64().map(DataType::Float),
+        ("BOOL", JsonValue::Bool(b)) => Some(DataType::Bit(*b)),
+        ("TEXT", JsonValue::String(s)) => Some(DataType::Text(s.clone())),
+        ("BLOB", JsonValue::Array(items)) => items
+            .iter()
+            .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .map(DataType::Blob),
+        // columns of other types take any single value
+        ("INTEGER" | "INT" | "FLOAT" | "REAL" | "NUMERIC" | "BOOL" | "TEXT" | "BLOB", _) => None,
+        (_, JsonValue::Number(n)) => n
+            .as_i64()
+            .map(DataType::Int)
+            .or_else(|| n.as_f64().map(DataType::Float)),
+        (_, JsonValue::Bool(b)) => Some(DataType::Bit(*b)),
+        (_, JsonValue::String(s)) => Some(DataType::Text(s.clone())),
+        _ => None,
+    };
+
+    converted.ok_or_else(|| {
+        DataBaseError::SerdeError(format!(
+            "Column \"{}\" of type {} cannot take {}",
+            column,
+            dtype.trim(),
+            val
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::definitions::DataBase;
+    use crate::query_builder as qr;
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Flag {
+        id: i64,
+        active: bool,
+        note: Option<String>,
+        seen: Option<bool>,
+        data: Vec<u8>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("databaser_serde_{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE F (id INTEGER PRIMARY KEY, active BOOL, note TEXT, seen BOOL, data BLOB);",
+            )
+            .unwrap();
+        let db = DataBase::from_file(&path).unwrap();
+
+        let flags = vec![
+            Flag {
+                id: 1,
+                active: true,
+                note: None,
+                seen: Some(false),
+                data: vec![0, 7, 255],
+            },
+            Flag {
+                id: 2,
+                active: false,
+                note: Some("spare".to_string()),
+                seen: None,
+                data: Vec::new(),
+            },
+        ];
+        for flag in &flags {
+            db.insert_from("F", flag).unwrap();
+        }
+        let read: Vec<Flag> = db.select_as(&qr::Select::new("F")).unwrap();
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read, flags);
+    }
+}
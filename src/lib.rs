@@ -0,0 +1,7 @@
+pub mod database;
+pub mod query_builder;
+mod readers;
+
+pub use database::record::DbRecord;
+pub use databaser_derive::DbRecord;
+pub use rusqlite;
@@ -1,44 +1,332 @@
-mod database;
-mod query_builder;
-mod readers;
+use databaser::database::configuration::print_config;
+use databaser::database::definitions::*;
+use databaser::database::errors::DataBaseError;
+use databaser::database::lint::check_config;
+use databaser::database::registry::DataBaseRegistry;
+use databaser::database::STD_CONFIG_PATH;
+use databaser::query_builder as qr;
+use databaser::DbRecord;
 
-use crate::query_builder::{Insert, ToQuery};
-use database::configuration::print_config;
-use database::definitions::*;
-use database::errors::DataBaseError;
-use database::STD_CONFIG_PATH;
+mod server;
+mod shell;
 
-use clap::{ArgGroup, Parser};
-use rusqlite::{params, Connection, Result};
-use std::collections::HashMap;
+use clap::{Parser, Subcommand};
+use rusqlite::Result;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
 
 /// CLI args parser
 #[derive(Parser, Debug)]
 #[command(name = "lab_db")]
-#[command(about = "An example CLI application", long_about = None)]
+#[command(about = "Laboratory equipment database", long_about = None)]
+#[command(after_help = EXIT_CODES)]
 struct Args {
-    /// Name of the person to greet
-    #[arg(short, long)]
-    name: String,
+    /// Path to the config file
+    #[arg(short, long, default_value = STD_CONFIG_PATH)]
+    config: String,
+
+    /// Name of the database from the config, the first one by default
+    #[arg(long)]
+    database: Option<String>,
+
+    /// Profile of the config overriding database paths, LAB_DB_PROFILE by default
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Walk through the rows of a table page by page
+    Browse {
+        table: String,
+        /// Number of rows on a page
+        #[arg(short, long, default_value_t = 20)]
+        page_size: usize,
+    },
+    /// Full-text search in a table, best matches first
+    Search {
+        table: String,
+        /// FTS5 query: words, "phrases", prefix*, OR/NOT
+        query: String,
+        /// Maximum number of results
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Make a timestamped backup of the database, older backups beyond the last N are removed
+    Backup {
+        /// Directory with backups
+        #[arg(short, long, default_value = BACKUP_DIR)]
+        dir: String,
+        /// Number of backups to keep
+        #[arg(short, long, default_value_t = 5)]
+        keep: usize,
+    },
+    /// Replace the database with a backup
+    Restore {
+        /// Backup file, the latest backup in the directory if not given
+        file: Option<String>,
+        /// Directory with backups
+        #[arg(short, long, default_value = BACKUP_DIR)]
+        dir: String,
+    },
+    /// Export tables and views as an SQL script
+    Dump {
+        /// Tables and views to export, all of them if none given
+        tables: Vec<String>,
+        /// Output file, standard output if not given
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Run an SQL script made by `dump`
+    Import { file: String },
+    /// Interactive shell for commands and SQL
+    Shell,
+    /// Serve tables of the database as REST endpoints with JSON
+    Serve {
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        /// Address to listen on, only this machine by default
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Origin of a web page allowed to call the server, e.g. http://localhost:3000.
+        /// Pages of other origins are refused
+        #[arg(long)]
+        allow_origin: Option<String>,
+    },
+    /// Check or show the config
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
 }
 
-fn main() -> Result<(), DataBaseError> {
-    let db = DataBase::from_config(STD_CONFIG_PATH)?;
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Report every problem of the config and its included files
+    Check,
+    /// Print databases, tables and views of the config
+    Show,
+}
+
+const BACKUP_DIR: &str = "backups";
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  2  wrong usage
+  3  invalid config
+  4  table or column not found
+  5  constraint violated
+  6  SQLite error
+  7  file or CSV error
+  8  data conversion error";
+
+/// Row of the `Labratory_Equipment` table
+#[derive(DbRecord, Debug)]
+#[db(table = "Labratory_Equipment")]
+struct EquipmentInfo {
+    #[db(primary_key)]
+    inventory_number: i64,
+    serial_number: i64,
+    department: String,
+    supply_date: String,
+    supply_number: i64,
+    supply_doc_number: i64,
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            // contexts first, the cause last
+            eprintln!("Error: {}", err);
+            let mut source = err.source();
+            while let Some(cause) = source {
+                eprintln!("  caused by: {}", cause.to_string().trim_end());
+                source = cause.source();
+            }
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), DataBaseError> {
+    // config commands work without opening databases, the config may be broken
+    if let Some(Command::Config { action }) = &args.command {
+        return match action {
+            ConfigAction::Check => check(&args.config),
+            ConfigAction::Show => print_config(&args.config, args.profile.as_deref()),
+        };
+    }
+
+    let mut registry = DataBaseRegistry::from_config(&args.config, args.profile.as_deref())?;
+    let name = match &args.database {
+        Some(name) => name.clone(),
+        None => registry.default_name().to_string(),
+    };
+    let db = registry.get_mut(&name)?;
+
+    match args.command {
+        Some(Command::Browse { table, page_size }) => browse(db, &table, page_size),
+        Some(Command::Search {
+            table,
+            query,
+            limit,
+        }) => search(db, &table, &query, limit),
+        Some(Command::Backup { dir, keep }) => {
+            let path = db.backup_into(&dir, keep, show_progress)?;
+            println!("\nBackup saved to {}", path.display());
+            Ok(())
+        }
+        Some(Command::Restore { file, dir }) => {
+            let file = match file {
+                Some(file) => file,
+                None => match db.backups(&dir)?.pop() {
+                    Some(path) => path.to_string_lossy().to_string(),
+                    None => {
+                        return Err(DataBaseError::ConfigError(format!(
+                            "No backups found in \"{}\"",
+                            dir
+                        )))
+                    }
+                },
+            };
+            db.restore_from(&file, show_progress)?;
+            println!("\nDatabase restored from {}", file);
+            Ok(())
+        }
+        Some(Command::Dump { tables, output }) => match output {
+            Some(path) => db.dump_to(&path, &tables),
+            None => {
+                print!("{}", db.dump(&tables)?);
+                Ok(())
+            }
+        },
+        Some(Command::Import { file }) => db.import_from(&file),
+        Some(Command::Shell) => shell::run(db),
+        Some(Command::Serve {
+            port,
+            host,
+            allow_origin,
+        }) => {
+            db.construct_tables()?;
+            server::run(db, &host, port, allow_origin.as_deref())
+        }
+        Some(Command::Config { .. }) => Ok(()),
+        None => demo(db),
+    }
+}
+
+fn browse(db: &DataBase, table: &str, page_size: usize) -> Result<(), DataBaseError> {
+    let mut after: Option<DataType> = None;
+    let mut stdin = io::stdin().lock();
+
+    loop {
+        let page = db.page(table, after.as_ref(), page_size)?;
+        for row in &page.rows {
+            let cells: Vec<String> = row.iter().map(|val| val.to_string()).collect();
+            println!("{}", cells.join(" | "));
+        }
+
+        after = match page.next {
+            Some(key) => Some(key),
+            None => return Ok(()),
+        };
+
+        print!("-- Enter for next page, q to quit -- ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer)? == 0 || answer.trim() == "q" {
+            return Ok(());
+        }
+    }
+}
+
+fn search(db: &DataBase, table: &str, query: &str, limit: usize) -> Result<(), DataBaseError> {
+    let hits = db.search(table, query, limit)?;
+    if hits.is_empty() {
+        println!("Nothing found");
+    }
+
+    for (place, hit) in hits.into_iter().enumerate() {
+        let cells: Vec<String> = hit.row.iter().map(|val| val.to_string()).collect();
+        println!("{}. {}", place + 1, cells.join(" | "));
+        for (column, text) in hit.highlights {
+            if text.contains('[') {
+                println!("\t{}: {}", column, text);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check(config_path: &str) -> Result<(), DataBaseError> {
+    let diagnostics = check_config(config_path);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+
+    // warnings are shown, but do not fail the check
+    match diagnostics.iter().filter(|d| !d.warning).count() {
+        0 => {
+            println!("{}: no problems found", config_path);
+            Ok(())
+        }
+        count => Err(DataBaseError::ConfigError(format!(
+            "{} problem(s) found in config",
+            count
+        ))),
+    }
+}
+
+fn show_progress(copied: usize, total: usize) {
+    print!("\rCopied {}/{} pages", copied, total);
+    let _ = io::stdout().flush();
+}
+
+fn demo(db: &DataBase) -> Result<(), DataBaseError> {
     db.show_structure();
     db.construct_tables()?;
     db.insert(
-        db.tables[0].name.as_str(),
+        "Departments",
         Some(&vec![
-            DataType::Int(1),
-            DataType::Int(2),
             DataType::Text("lab".to_string()),
-            DataType::Text("12.12.24".to_string()),
-            DataType::Int(3),
-            DataType::Int(4),
+            DataType::Text("Ivanov".to_string()),
+            DataType::Text("101".to_string()),
         ]),
     )?;
+    db.insert_record(&EquipmentInfo {
+        inventory_number: 1,
+        serial_number: 2,
+        department: "lab".to_string(),
+        supply_date: "12.12.24".to_string(),
+        supply_number: 3,
+        supply_doc_number: 4,
+    })?;
 
     db.show_all_data()?;
 
+    let equipment: Vec<EquipmentInfo> =
+        db.select_records(&qr::Select::new(&EquipmentInfo::table().name))?;
+    println!("{:?}", equipment);
+
+    // equipment with head and room of its department
+    let with_departments = qr::Select::new("Labratory_Equipment")
+        .alias("e")
+        .inner_join("Departments", Some("d"), "e.department = d.name")
+        .columns(&vec![
+            "e.inventory_number".to_string(),
+            "d.head".to_string(),
+            "d.room".to_string(),
+        ]);
+    for row in db.select(&with_departments)? {
+        println!("{:?}", row);
+    }
+
     Ok(())
 }
@@ -1,15 +1,61 @@
-use crate::database::definitions::DataType;
+use crate::database::definitions::{DataType, ForeignKey, DELETED_AT};
 use core::panic;
+use serde::Deserialize;
 
+#[derive(Clone)]
 pub struct Select {
     table_name: String,
+    alias: Option<String>,
     columns: Option<Vec<String>>,
+    joins: Vec<Join>,
     condition: Option<String>,
+    distinct: bool,
+    order_by: Vec<OrderBy>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    hidden_deleted: Vec<String>, // tables whose soft deleted rows are filtered out
+    with_deleted: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+#[derive(Clone)]
+pub struct OrderBy {
+    pub column: String,
+    pub order: Order,
+    pub nulls: Option<Nulls>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinKind {
+    #[default]
+    Inner,
+    Left,
+}
+
+#[derive(Clone)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table_name: String,
+    pub alias: Option<String>,
+    pub on: String,
 }
 
 pub struct CreateTable {
     table_name: String,
     cols_defs: Option<Vec<String>>,
+    foreign_keys: Vec<ForeignKey>,
 }
 
 pub struct Insert {
@@ -19,6 +65,37 @@ pub struct Insert {
     parameterization: bool,
 }
 
+// values are always passed as parameters
+pub struct Update {
+    table_name: String,
+    columns: Option<Vec<String>>,
+    condition: Option<String>,
+}
+
+pub struct Delete {
+    table_name: String,
+    condition: Option<String>,
+}
+
+pub struct CreateIndex {
+    index_name: String,
+    table_name: String,
+    columns: Option<Vec<String>>,
+    unique: bool,
+    condition: Option<String>,
+}
+
+// FTS5 table `<table>_fts` with external content, kept in sync with the table by triggers
+pub struct CreateFullText {
+    table_name: String,
+    columns: Option<Vec<String>>,
+}
+
+pub struct CreateView {
+    view_name: String,
+    select: Option<String>,
+}
+
 pub struct Count {
     table_name: String,
     column: Option<String>,
@@ -30,11 +107,76 @@ impl Select {
     pub fn new(table: &str) -> Self {
         Select {
             table_name: table.to_string(),
+            alias: None,
             columns: None,
+            joins: Vec::new(),
             condition: None,
+            distinct: false,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hidden_deleted: Vec::new(),
+            with_deleted: false,
         }
     }
 
+    pub fn distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+
+    // can be called several times, rows are sorted by columns in order of calls
+    pub fn order_by(mut self, column: &str, order: Order) -> Self {
+        self.order_by.push(OrderBy {
+            column: column.to_owned(),
+            order,
+            nulls: None,
+        });
+        self
+    }
+
+    pub fn order_by_nulls(mut self, column: &str, order: Order, nulls: Nulls) -> Self {
+        self.order_by.push(OrderBy {
+            column: column.to_owned(),
+            order,
+            nulls: Some(nulls),
+        });
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.to_owned());
+        self
+    }
+
+    pub fn inner_join(self, table: &str, alias: Option<&str>, on: &str) -> Self {
+        self.join(JoinKind::Inner, table, alias, on)
+    }
+
+    pub fn left_join(self, table: &str, alias: Option<&str>, on: &str) -> Self {
+        self.join(JoinKind::Left, table, alias, on)
+    }
+
+    pub fn join(mut self, kind: JoinKind, table: &str, alias: Option<&str>, on: &str) -> Self {
+        self.joins.push(Join {
+            kind,
+            table_name: table.to_owned(),
+            alias: alias.map(|a| a.to_owned()),
+            on: on.to_owned(),
+        });
+        self
+    }
+
     pub fn columns(mut self, columns: &Vec<String>) -> Self {
         self.columns = Some(columns.to_owned());
         self
@@ -44,6 +186,50 @@ impl Select {
         self.condition = Some(condition.to_owned());
         self
     }
+
+    // rows of the table (under every alias it is used with) having `deleted_at` set are skipped
+    pub fn hide_deleted(mut self, table: &str) -> Self {
+        self.hidden_deleted.push(table.to_owned());
+        self
+    }
+
+    // asks `DataBase` to keep soft deleted rows in results
+    pub fn with_deleted(mut self) -> Self {
+        self.with_deleted = true;
+        self
+    }
+
+    pub fn includes_deleted(&self) -> bool {
+        self.with_deleted
+    }
+
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    pub fn selected_columns(&self) -> Option<&Vec<String>> {
+        self.columns.as_ref()
+    }
+
+    // every table the query reads from with its alias, the main table goes first
+    pub fn sources(&self) -> Vec<(&str, Option<&str>)> {
+        let mut sources = vec![(self.table_name.as_str(), self.alias.as_deref())];
+        for join in &self.joins {
+            sources.push((join.table_name.as_str(), join.alias.as_deref()));
+        }
+        sources
+    }
+
+    fn live_rows(&self, table: &str, alias: Option<&str>) -> Option<String> {
+        if !self.hidden_deleted.iter().any(|t| t == table) {
+            return None;
+        }
+        Some(format!(
+            "{}.{} IS NULL",
+            quote_ident(alias.unwrap_or(table)),
+            quote_ident(DELETED_AT)
+        ))
+    }
 }
 
 impl CreateTable {
@@ -51,6 +237,7 @@ impl CreateTable {
         CreateTable {
             table_name: table.to_owned(),
             cols_defs: None,
+            foreign_keys: Vec::new(),
         }
     }
 
@@ -58,6 +245,11 @@ impl CreateTable {
         self.cols_defs = Some(col_definitions.to_owned());
         self
     }
+
+    pub fn foreign_key(mut self, fkey: &ForeignKey) -> Self {
+        self.foreign_keys.push(fkey.clone());
+        self
+    }
 }
 
 impl Insert {
@@ -98,7 +290,7 @@ impl Insert {
                     panic!("Insert: Number of columns and values do not match");
                 } else {
                     self.vals = vals.clone();
-                    return self;
+                    self
                 }
             }
             None => self.parameterize(),
@@ -106,6 +298,105 @@ impl Insert {
     }
 }
 
+impl Update {
+    pub fn new(table_name: &str) -> Self {
+        Update {
+            table_name: table_name.to_owned(),
+            columns: None,
+            condition: None,
+        }
+    }
+
+    pub fn columns(mut self, columns: &[String]) -> Self {
+        self.columns = Some(columns.to_owned());
+        self
+    }
+
+    pub fn condition(mut self, condition: &str) -> Self {
+        self.condition = Some(condition.to_owned());
+        self
+    }
+}
+
+impl Delete {
+    pub fn new(table_name: &str) -> Self {
+        Delete {
+            table_name: table_name.to_owned(),
+            condition: None,
+        }
+    }
+
+    pub fn condition(mut self, condition: &str) -> Self {
+        self.condition = Some(condition.to_owned());
+        self
+    }
+}
+
+impl CreateIndex {
+    pub fn new(index_name: &str, table_name: &str) -> Self {
+        CreateIndex {
+            index_name: index_name.to_owned(),
+            table_name: table_name.to_owned(),
+            columns: None,
+            unique: false,
+            condition: None,
+        }
+    }
+
+    pub fn columns(mut self, columns: &[String]) -> Self {
+        self.columns = Some(columns.to_owned());
+        self
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    // makes the index partial
+    pub fn condition(mut self, condition: &str) -> Self {
+        self.condition = Some(condition.to_owned());
+        self
+    }
+}
+
+impl CreateFullText {
+    pub fn new(table_name: &str) -> Self {
+        CreateFullText {
+            table_name: table_name.to_owned(),
+            columns: None,
+        }
+    }
+
+    pub fn columns(mut self, columns: &[String]) -> Self {
+        self.columns = Some(columns.to_owned());
+        self
+    }
+
+    pub fn fts_table_name(&self) -> String {
+        full_text_table(&self.table_name)
+    }
+}
+
+pub fn full_text_table(table_name: &str) -> String {
+    format!("{}_fts", table_name)
+}
+
+impl CreateView {
+    pub fn new(view_name: &str) -> Self {
+        CreateView {
+            view_name: view_name.to_owned(),
+            select: None,
+        }
+    }
+
+    // SELECT statement the view is defined with
+    pub fn select(mut self, select: &str) -> Self {
+        self.select = Some(select.trim().trim_end_matches(';').to_owned());
+        self
+    }
+}
+
 impl Count {
     pub fn new(table_name: &str) -> Self {
         Count {
@@ -134,11 +425,11 @@ impl Count {
 
 // converts vector of Strings into String, where each string is separated
 // with comma to make an insert for querry
-fn cols_to_insert(columns: &Vec<String>) -> String {
+fn cols_to_insert(columns: &[String]) -> String {
     let mut result = String::new();
 
     for (idx, col) in columns.iter().enumerate() {
-        result.push_str(col);
+        result.push_str(&quote_ident(col));
         if idx != columns.len() - 1 {
             result.push_str(", ");
         }
@@ -147,6 +438,29 @@ fn cols_to_insert(columns: &Vec<String>) -> String {
     result
 }
 
+// Table, column, alias and index names are always quoted, so names with spaces or reserved
+// words work and a name cannot break out of the query. Conditions (WHERE, ON) are raw SQL.
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// column of a select: `col`, `alias.col`, `*` or `alias.*`
+fn quote_column(column: &str) -> String {
+    if column == "*" {
+        return column.to_string();
+    }
+
+    match column.split_once('.') {
+        Some((source, "*")) => format!("{}.*", quote_ident(source)),
+        Some((source, col)) => format!("{}.{}", quote_ident(source), quote_ident(col)),
+        None => quote_ident(column),
+    }
+}
+
+pub fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
 pub trait ToQuery {
     fn to_query(&self) -> String;
 }
@@ -154,36 +468,188 @@ pub trait ToQuery {
 impl ToQuery for Select {
     fn to_query(&self) -> String {
         let columns_part = match &self.columns {
-            Some(cols) => cols.join(", "),
+            Some(cols) => cols
+                .iter()
+                .map(|col| quote_column(col))
+                .collect::<Vec<String>>()
+                .join(", "),
             None => "*".to_string(), // Default to all columns if none are specified
         };
 
-        let condition_part = match &self.condition {
-            Some(cond) => format!(" WHERE {}", cond),
-            None => String::new(),
+        let mut conditions: Vec<String> = self.condition.iter().cloned().collect();
+        if let Some(live) = self.live_rows(&self.table_name, self.alias.as_deref()) {
+            if let Some(cond) = conditions.first_mut() {
+                *cond = format!("({})", cond);
+            }
+            conditions.push(live);
+        }
+        let condition_part = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
         };
 
+        let mut from_part = quote_ident(&self.table_name);
+        if let Some(alias) = &self.alias {
+            from_part.push_str(&format!(" AS {}", quote_ident(alias)));
+        }
+        for join in &self.joins {
+            from_part.push_str(match join.kind {
+                JoinKind::Inner => " INNER JOIN ",
+                JoinKind::Left => " LEFT JOIN ",
+            });
+            from_part.push_str(&quote_ident(&join.table_name));
+            if let Some(alias) = &join.alias {
+                from_part.push_str(&format!(" AS {}", quote_ident(alias)));
+            }
+            // condition goes into ON so that LEFT JOIN still keeps rows without a live match
+            match self.live_rows(&join.table_name, join.alias.as_deref()) {
+                Some(live) => from_part.push_str(&format!(" ON ({}) AND {}", join.on, live)),
+                None => from_part.push_str(&format!(" ON {}", join.on)),
+            }
+        }
+
+        let mut tail_part = String::new();
+        for (idx, order_by) in self.order_by.iter().enumerate() {
+            tail_part.push_str(if idx == 0 { " ORDER BY " } else { ", " });
+            tail_part.push_str(&quote_column(&order_by.column));
+            tail_part.push_str(match order_by.order {
+                Order::Asc => " ASC",
+                Order::Desc => " DESC",
+            });
+            match order_by.nulls {
+                Some(Nulls::First) => tail_part.push_str(" NULLS FIRST"),
+                Some(Nulls::Last) => tail_part.push_str(" NULLS LAST"),
+                None => {}
+            }
+        }
+        match (self.limit, self.offset) {
+            (Some(limit), Some(offset)) => {
+                tail_part.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset))
+            }
+            (Some(limit), None) => tail_part.push_str(&format!(" LIMIT {}", limit)),
+            // sqlite does not accept OFFSET without LIMIT, -1 means no limit
+            (None, Some(offset)) => tail_part.push_str(&format!(" LIMIT -1 OFFSET {}", offset)),
+            (None, None) => {}
+        }
+
         format!(
-            "SELECT {} FROM {}{};",
-            columns_part, self.table_name, condition_part
+            "SELECT {}{} FROM {}{}{};",
+            if self.distinct { "DISTINCT " } else { "" },
+            columns_part,
+            from_part,
+            condition_part,
+            tail_part
         )
     }
 }
 
 impl ToQuery for CreateTable {
     fn to_query(&self) -> String {
-        let cols_part = match &self.cols_defs {
+        let mut cols_part = match &self.cols_defs {
             Some(cols) => cols.join(", "),
             None => panic!("No column definitions provided for table creation"),
         };
 
+        for fkey in &self.foreign_keys {
+            cols_part.push_str(&format!(
+                ", FOREIGN KEY ({}) REFERENCES {} ({})",
+                quote_ident(&fkey.column),
+                quote_ident(&fkey.ref_table),
+                quote_ident(&fkey.ref_column)
+            ));
+            if let Some(action) = &fkey.on_delete {
+                cols_part.push_str(&format!(" ON DELETE {}", action));
+            }
+            if let Some(action) = &fkey.on_update {
+                cols_part.push_str(&format!(" ON UPDATE {}", action));
+            }
+        }
+
         format!(
             "CREATE TABLE IF NOT EXISTS {} ({});",
-            self.table_name, cols_part
+            quote_ident(&self.table_name),
+            cols_part
+        )
+    }
+}
+
+impl ToQuery for CreateIndex {
+    fn to_query(&self) -> String {
+        let cols_part = match &self.columns {
+            Some(cols) => cols_to_insert(cols),
+            None => panic!("No columns provided for index creation"),
+        };
+
+        let condition_part = match &self.condition {
+            Some(cond) => format!(" WHERE {}", cond),
+            None => String::new(),
+        };
+
+        format!(
+            "CREATE {}INDEX IF NOT EXISTS {} ON {} ({}){};",
+            if self.unique { "UNIQUE " } else { "" },
+            quote_ident(&self.index_name),
+            quote_ident(&self.table_name),
+            cols_part,
+            condition_part
+        )
+    }
+}
+
+// several statements, to be run with `execute_batch`
+impl ToQuery for CreateFullText {
+    fn to_query(&self) -> String {
+        let cols = match &self.columns {
+            Some(cols) => cols,
+            None => panic!("No columns provided for full-text index"),
+        };
+
+        let table = quote_ident(&self.table_name);
+        let fts = quote_ident(&self.fts_table_name());
+        let trigger = |suffix: &str| quote_ident(&format!("{}_{}", self.fts_table_name(), suffix));
+        let cols_part = cols_to_insert(cols);
+        let prefixed = |prefix: &str| {
+            cols.iter()
+                .map(|col| format!("{}.{}", prefix, quote_ident(col)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let (new_vals, old_vals) = (prefixed("new"), prefixed("old"));
+
+        format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS {fts} USING fts5({cols_part}, content={content});
+CREATE TRIGGER IF NOT EXISTS {ins} AFTER INSERT ON {table} BEGIN
+    INSERT INTO {fts} (rowid, {cols_part}) VALUES (new.rowid, {new_vals});
+END;
+CREATE TRIGGER IF NOT EXISTS {del} AFTER DELETE ON {table} BEGIN
+    INSERT INTO {fts} ({fts}, rowid, {cols_part}) VALUES ('delete', old.rowid, {old_vals});
+END;
+CREATE TRIGGER IF NOT EXISTS {upd} AFTER UPDATE ON {table} BEGIN
+    INSERT INTO {fts} ({fts}, rowid, {cols_part}) VALUES ('delete', old.rowid, {old_vals});
+    INSERT INTO {fts} (rowid, {cols_part}) VALUES (new.rowid, {new_vals});
+END;",
+            content = quote_literal(&self.table_name),
+            ins = trigger("ai"),
+            del = trigger("ad"),
+            upd = trigger("au"),
         )
     }
 }
 
+impl ToQuery for CreateView {
+    fn to_query(&self) -> String {
+        match &self.select {
+            Some(select) => format!(
+                "CREATE VIEW IF NOT EXISTS {} AS {};",
+                quote_ident(&self.view_name),
+                select
+            ),
+            None => panic!("No select statement provided for view creation"),
+        }
+    }
+}
+
 impl ToQuery for Insert {
     fn to_query(&self) -> String {
         if self.columns.is_none() {
@@ -195,23 +661,32 @@ impl ToQuery for Insert {
         let mut cols_insert = String::new();
         let mut vals_insert = String::new();
         if let Some(cols_defs) = &self.columns {
-            for (idx, col_def) in cols_defs.iter().enumerate() {
-                cols_insert.push_str(col_def.as_str());
-                if idx != cols_defs.len() - 1 {
-                    cols_insert.push_str(", ");
-                }
-            }
+            cols_insert = cols_to_insert(cols_defs);
         }
 
         if let Some(vals) = &self.vals {
             for (idx, val) in vals.iter().enumerate() {
                 match val {
                     DataType::Int(v) => vals_insert.push_str(&v.to_string()),
-                    DataType::Float(v) => vals_insert.push_str(&v.to_string()),
+                    // sqlite reads too large literals as infinity
+                    DataType::Float(v) if v.is_infinite() => {
+                        vals_insert.push_str(if *v > 0.0 { "1e999" } else { "-1e999" })
+                    }
+                    // sqlite has no NaN, bound NaN is stored as NULL as well
+                    DataType::Float(v) if v.is_nan() => vals_insert.push_str("NULL"),
+                    DataType::Float(v) => vals_insert.push_str(&format!("{:?}", v)),
                     DataType::Bit(v) => {
                         vals_insert.push_str(&format!("{}", if *v { 1 } else { 0 }))
                     }
-                    DataType::Text(v) => vals_insert.push_str(&format!("\"{}\"", v)),
+                    DataType::Text(v) => vals_insert.push_str(&quote_literal(v)),
+                    DataType::Blob(v) => {
+                        vals_insert.push_str("X'");
+                        for byte in v {
+                            vals_insert.push_str(&format!("{:02X}", byte));
+                        }
+                        vals_insert.push('\'');
+                    }
+                    DataType::Null => vals_insert.push_str("NULL"),
                     DataType::Param(v) => vals_insert.push_str(v),
                 }
 
@@ -223,7 +698,45 @@ impl ToQuery for Insert {
 
         format!(
             "INSERT INTO {} ({}) VALUES ({});",
-            self.table_name, cols_insert, vals_insert
+            quote_ident(&self.table_name),
+            cols_insert,
+            vals_insert
+        )
+    }
+}
+
+impl ToQuery for Update {
+    fn to_query(&self) -> String {
+        let set_part = match &self.columns {
+            Some(cols) => cols
+                .iter()
+                .map(|col| format!("{} = ?", quote_ident(col)))
+                .collect::<Vec<String>>()
+                .join(", "),
+            None => panic!("No columns provided for UPDATE"),
+        };
+
+        format!(
+            "UPDATE {} SET {}{};",
+            quote_ident(&self.table_name),
+            set_part,
+            match &self.condition {
+                Some(cond) => format!(" WHERE {}", cond),
+                None => String::new(),
+            }
+        )
+    }
+}
+
+impl ToQuery for Delete {
+    fn to_query(&self) -> String {
+        format!(
+            "DELETE FROM {}{};",
+            quote_ident(&self.table_name),
+            match &self.condition {
+                Some(cond) => format!(" WHERE {}", cond),
+                None => String::new(),
+            }
         )
     }
 }
@@ -231,11 +744,40 @@ impl ToQuery for Insert {
 impl ToQuery for Count {
     fn to_query(&self) -> String {
         format!(
-            "COUNT ({}{}) FROM {}{};",
+            "SELECT COUNT({}{}) FROM {}{};",
             if self.distinct { "DISTINCT " } else { "" },
-            self.column.clone().unwrap_or("*".to_string()),
-            self.table_name,
-            self.condition.clone().unwrap_or("".to_string())
+            match &self.column {
+                Some(col) => quote_ident(col),
+                None => "*".to_string(),
+            },
+            quote_ident(&self.table_name),
+            match &self.condition {
+                Some(cond) => format!(" WHERE {}", cond),
+                None => String::new(),
+            }
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_non_finite_floats() {
+        let q = Insert::new("T")
+            .columns(&vec!["a".to_string(), "b".to_string(), "c".to_string()])
+            .values(Some(vec![
+                DataType::Float(f64::NAN),
+                DataType::Float(f64::INFINITY),
+                DataType::Float(f64::NEG_INFINITY),
+            ]))
+            .to_query();
+        assert!(q.ends_with("VALUES (NULL, 1e999, -1e999);"), "{}", q);
+
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE T (a FLOAT, b FLOAT, c FLOAT);")
+            .unwrap();
+        conn.execute_batch(&q).unwrap();
+    }
+}
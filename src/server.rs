@@ -0,0 +1,510 @@
+// REST server of `lab_db serve`. Every table and view of the config is a resource:
+//
+//   GET    /tables                                  tables and views with their columns
+//   GET    /tables/<table>?col=value&after=&limit=  rows with given values, page by page
+//   GET    /tables/<table>/<key>                    row with the primary key
+//   POST   /tables/<table>                          create a row
+//   PUT    /tables/<table>/<key>                    change given columns (PATCH as well)
+//   DELETE /tables/<table>/<key>                    delete a row
+//
+// Rows are JSON objects of column names and values, values of bodies are checked against the
+// types of the columns. Requests are served one at a time on the connection of the database,
+// which is plenty for a form used by lab staff. Web pages of other origins are refused, except
+// the one given by `--allow-origin`, so a page open in the browser cannot change the inventory.
+
+use databaser::database::audit::key_column;
+use databaser::database::definitions::*;
+use databaser::database::errors::DataBaseError;
+use databaser::database::serialization::{json_to_datatype, to_json};
+
+use serde_json::{json, Map, Value as JsonValue};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+const MAX_BODY: usize = 1 << 20;
+
+struct Request {
+    method: String,
+    path: Vec<String>, // decoded segments
+    query: Vec<(String, String)>,
+    origin: Option<String>, // sent by browsers with requests of web pages
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: Option<JsonValue>,
+}
+
+pub fn run(
+    db: &DataBase,
+    host: &str,
+    port: u16,
+    allow_origin: Option<&str>,
+) -> Result<(), DataBaseError> {
+    let listener = TcpListener::bind((host, port))?;
+    println!("Serving tables on http://{}:{}/tables", host, port);
+
+    for stream in listener.incoming() {
+        // a broken connection does not stop the server
+        let result = stream.and_then(|mut stream| handle(db, &mut stream, allow_origin));
+        if let Err(err) = result {
+            eprintln!("Error: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle(db: &DataBase, stream: &mut TcpStream, allow_origin: Option<&str>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut cors_origin = None;
+    let response = match read_request(stream) {
+        Ok(request) => {
+            // requests without Origin come from other programs than browsers
+            let response = match request.origin.as_deref() {
+                Some(origin) if Some(origin) == allow_origin => {
+                    cors_origin = allow_origin;
+                    route(db, &request)
+                }
+                Some(_) => error_response(403, "Origin is not allowed"),
+                None => route(db, &request),
+            };
+            println!(
+                "{} /{} {}",
+                request.method,
+                request.path.join("/"),
+                response.status
+            );
+            response
+        }
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            error_response(400, &err.to_string())
+        }
+        Err(err) => return Err(err),
+    };
+
+    write_response(stream, &response, cors_origin)
+}
+
+fn route(db: &DataBase, request: &Request) -> Response {
+    let path: Vec<&str> = request.path.iter().map(String::as_str).collect();
+    let result = match (request.method.as_str(), path.as_slice()) {
+        // preflight of browsers before requests with JSON bodies, the origin is checked already
+        ("OPTIONS", _) => Ok(Response {
+            status: 204,
+            body: None,
+        }),
+        ("GET", ["tables"]) => Ok(list_tables(db)),
+        ("GET", ["tables", table]) => list_rows(db, table, &request.query),
+        ("GET", ["tables", table, key]) => get_row(db, table, key),
+        ("POST", ["tables", table]) => create_row(db, table, &request.body),
+        ("PUT" | "PATCH", ["tables", table, key]) => update_row(db, table, key, &request.body),
+        ("DELETE", ["tables", table, key]) => delete_row(db, table, key),
+        (_, ["tables", ..]) => Ok(error_response(405, "Method not allowed")),
+        _ => Ok(error_response(
+            404,
+            "Not found, resources are under /tables",
+        )),
+    };
+
+    result.unwrap_or_else(|err| error_response(status_of(&err), &err.report()))
+}
+
+fn list_tables(db: &DataBase) -> Response {
+    let tables: Vec<JsonValue> = db
+        .tables
+        .iter()
+        .map(|table| {
+            let columns: Vec<JsonValue> = table
+                .columns
+                .iter()
+                .map(|(name, dtype)| {
+                    let description = table
+                        .column_docs
+                        .iter()
+                        .find(|doc| doc.column == *name)
+                        .and_then(|doc| doc.description.clone());
+                    json!({
+                        "name": name,
+                        "type": dtype,
+                        "label": table.label(name),
+                        "description": description,
+                    })
+                })
+                .collect();
+            json!({
+                "name": table.name,
+                "view": table.view.is_some(),
+                "primary_key": key_column(table),
+                "columns": columns,
+            })
+        })
+        .collect();
+
+    ok(200, JsonValue::Array(tables))
+}
+
+fn list_rows(
+    db: &DataBase,
+    table_name: &str,
+    query: &[(String, String)],
+) -> Result<Response, DataBaseError> {
+    let table = find_table(db, table_name)?;
+
+    let mut limit = DEFAULT_LIMIT;
+    let mut after: Option<DataType> = None;
+    let mut filters: Vec<(String, DataType)> = Vec::new();
+    for (name, value) in query {
+        match name.as_str() {
+            "limit" => {
+                limit = match value.parse::<usize>() {
+                    Ok(limit) if limit > 0 => limit.min(MAX_LIMIT),
+                    _ => return Ok(error_response(400, "Limit must be a positive number")),
+                }
+            }
+            "after" => after = Some(parse_key(table, value)?),
+            column => {
+                let dtype = column_type(table, column)?;
+                filters.push((column.to_string(), parse_text(column, dtype, value)?));
+            }
+        }
+    }
+
+    let page = db.page_filtered(table_name, &filters, after.as_ref(), limit)?;
+    let rows: Vec<JsonValue> = page
+        .rows
+        .into_iter()
+        .map(|row| row_json(table, row))
+        .collect();
+    Ok(ok(
+        200,
+        json!({ "rows": rows, "next": page.next.map(to_json) }),
+    ))
+}
+
+fn get_row(db: &DataBase, table_name: &str, key: &str) -> Result<Response, DataBaseError> {
+    let table = find_table(db, table_name)?;
+    let key = parse_key(table, key)?;
+
+    Ok(match db.get(table_name, &key)? {
+        Some(row) => ok(200, row_json(table, row)),
+        None => row_not_found(table_name, &key),
+    })
+}
+
+fn create_row(db: &DataBase, table_name: &str, body: &[u8]) -> Result<Response, DataBaseError> {
+    let table = find_table(db, table_name)?;
+    let values = body_values(table, body)?;
+
+    let key = db.insert_values(table_name, &values)?;
+    let row = db.get(table_name, &key)?;
+
+    Ok(ok(
+        201,
+        row.map_or(JsonValue::Null, |row| row_json(table, row)),
+    ))
+}
+
+fn update_row(
+    db: &DataBase,
+    table_name: &str,
+    key: &str,
+    body: &[u8],
+) -> Result<Response, DataBaseError> {
+    let table = find_table(db, table_name)?;
+    let key = parse_key(table, key)?;
+    let values = body_values(table, body)?;
+    if values.is_empty() {
+        return Ok(error_response(400, "Body has no columns to change"));
+    }
+
+    if !db.update(table_name, &key, &values)? {
+        return Ok(row_not_found(table_name, &key));
+    }
+    // primary key itself may be changed
+    let new_key = values
+        .iter()
+        .find(|(col, _)| *col == table.primary_key)
+        .map_or(key, |(_, val)| val.clone());
+    let row = db.get(table_name, &new_key)?;
+
+    Ok(ok(
+        200,
+        row.map_or(JsonValue::Null, |row| row_json(table, row)),
+    ))
+}
+
+fn delete_row(db: &DataBase, table_name: &str, key: &str) -> Result<Response, DataBaseError> {
+    let table = find_table(db, table_name)?;
+    let key = parse_key(table, key)?;
+
+    Ok(match db.delete(table_name, &key)? {
+        true => Response {
+            status: 204,
+            body: None,
+        },
+        false => row_not_found(table_name, &key),
+    })
+}
+
+fn find_table<'a>(db: &'a DataBase, table_name: &str) -> Result<&'a Table, DataBaseError> {
+    db.tables
+        .iter()
+        .find(|t| t.name == table_name)
+        .ok_or_else(|| DataBaseError::TableNotFound(format!("No table \"{}\"", table_name)))
+}
+
+fn column_type<'a>(table: &'a Table, column: &str) -> Result<&'a str, DataBaseError> {
+    table
+        .columns
+        .iter()
+        .find(|(name, _)| name == column)
+        .map(|(_, dtype)| dtype.as_str())
+        .ok_or_else(|| {
+            DataBaseError::ColumnNotFound(format!(
+                "No column \"{}\" in table \"{}\"",
+                column, table.name
+            ))
+        })
+}
+
+// key from the path, rowid of tables without primary key is an integer
+fn parse_key(table: &Table, text: &str) -> Result<DataType, DataBaseError> {
+    let key = key_column(table);
+    let dtype = match key {
+        "rowid" => "INTEGER",
+        key => column_type(table, key)?,
+    };
+
+    parse_text(key, dtype, text)
+}
+
+// values in paths and queries are text, numbers are read by the column type
+fn parse_text(column: &str, dtype: &str, text: &str) -> Result<DataType, DataBaseError> {
+    let val = match serde_json::from_str::<JsonValue>(text) {
+        Ok(val @ (JsonValue::Number(_) | JsonValue::Bool(_) | JsonValue::Null)) => val,
+        _ => JsonValue::String(text.to_string()),
+    };
+    match json_to_datatype(column, dtype, &val) {
+        Ok(val) => Ok(val),
+        // e.g. "007" in a TEXT column
+        Err(_) if !val.is_string() => {
+            json_to_datatype(column, dtype, &JsonValue::String(text.to_string()))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// body of POST and PUT: JSON object of columns and their new values
+fn body_values(table: &Table, body: &[u8]) -> Result<Vec<(String, DataType)>, DataBaseError> {
+    let object: Map<String, JsonValue> = match serde_json::from_slice(body) {
+        Ok(JsonValue::Object(object)) => object,
+        Ok(_) => {
+            return Err(DataBaseError::SerdeError(
+                "Body must be a JSON object of columns and values".to_string(),
+            ))
+        }
+        Err(err) => {
+            return Err(DataBaseError::SerdeError(format!(
+                "Body is not valid JSON: {}",
+                err
+            )))
+        }
+    };
+
+    let mut values = Vec::new();
+    for (column, val) in &object {
+        let dtype = column_type(table, column)?;
+        values.push((column.clone(), json_to_datatype(column, dtype, val)?));
+    }
+
+    Ok(values)
+}
+
+fn row_json(table: &Table, row: Vec<DataType>) -> JsonValue {
+    let mut object = Map::new();
+    for ((name, _), val) in table.columns.iter().zip(row) {
+        object.insert(name.clone(), to_json(val));
+    }
+
+    JsonValue::Object(object)
+}
+
+fn row_not_found(table_name: &str, key: &DataType) -> Response {
+    error_response(
+        404,
+        &format!("No row with key {} in table \"{}\"", key, table_name),
+    )
+}
+
+fn ok(status: u16, body: JsonValue) -> Response {
+    Response {
+        status,
+        body: Some(body),
+    }
+}
+
+fn error_response(status: u16, message: &str) -> Response {
+    ok(status, json!({ "error": message }))
+}
+
+fn status_of(err: &DataBaseError) -> u16 {
+    match err.exit_code() {
+        // config problems here come from requests, e.g. changes of views
+        3 | 8 => 400,
+        4 => 404,
+        5 => 409,
+        _ => 500,
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("Malformed request line")),
+    };
+
+    let mut content_length = 0;
+    let mut origin = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("Headers are not finished"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("Invalid Content-Length"))?;
+            } else if name.trim().eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_string());
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(invalid("Body is too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Ok(Request {
+        method,
+        path: path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(decode)
+            .collect(),
+        query: query
+            .split('&')
+            .filter(|s| !s.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_query(name), decode_query(value))
+            })
+            .collect(),
+        origin,
+        body,
+    })
+}
+
+// percent-encoding of URLs
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' if idx + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        idx += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// `+` is a space only in queries of forms, in paths it is the sign itself
+fn decode_query(text: &str) -> String {
+    decode(&text.replace('+', " "))
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    response: &Response,
+    cors_origin: Option<&str>,
+) -> io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    };
+    let body = response
+        .body
+        .as_ref()
+        .map(|body| body.to_string())
+        .unwrap_or_default();
+
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+    head.push_str("Content-Type: application/json\r\n");
+    head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    // the form may be served from another port or host
+    if let Some(origin) = cors_origin {
+        head.push_str(&format!("Access-Control-Allow-Origin: {}\r\n", origin));
+        head.push_str("Access-Control-Allow-Methods: GET, POST, PUT, PATCH, DELETE, OPTIONS\r\n");
+        head.push_str("Access-Control-Allow-Headers: Content-Type\r\n");
+        head.push_str("Vary: Origin\r\n");
+    }
+    head.push_str("Connection: close\r\n\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_percent_and_plus() {
+        assert_eq!(decode("a%20b%2Bc"), "a b+c");
+        assert_eq!(decode("a+b"), "a+b");
+        assert_eq!(decode_query("a+b%2B"), "a b+");
+        assert_eq!(decode("%C3%A9"), "é");
+        // broken escapes are kept as they are
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+    }
+}
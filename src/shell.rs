@@ -0,0 +1,396 @@
+// Interactive shell of `lab_db`. A line is either a command
+//
+//   insert <table> col=value ...
+//   update <table> <key> col=value ...
+//   delete <table> <key>
+//   select <table> [col ...] [where <condition>]
+//   search <table> <query>
+//   .tables, .schema <table>, .help, .quit
+//
+// or raw SQL. Words after a command are split on spaces, 'quoted text' keeps spaces.
+// Values are integers, floats, NULL or text.
+
+use databaser::database::definitions::*;
+use databaser::database::errors::DataBaseError;
+use databaser::query_builder as qr;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+const HISTORY_FILE: &str = ".lab_db_history";
+const COMMANDS: [&str; 9] = [
+    "insert", "update", "delete", "select", "search", ".tables", ".schema", ".help", ".quit",
+];
+const HELP: &str = "\
+insert <table> col=value ...            insert a row
+update <table> <key> col=value ...      change a row
+delete <table> <key>                    delete a row
+select <table> [col ...] [where <sql>]  show rows
+search <table> <query>                  full-text search
+.tables                                 list tables and views
+.schema <table>                         show columns of a table
+.quit                                   leave the shell
+anything else is run as SQL";
+
+pub fn run(db: &DataBase) -> Result<(), DataBaseError> {
+    let mut editor: Editor<NameCompleter, DefaultHistory> =
+        Editor::new().map_err(readline_error)?;
+    editor.set_helper(Some(NameCompleter::new(&db.tables)));
+    let history = history_path();
+    // there is no history on the first start
+    let _ = editor.load_history(&history);
+
+    println!("lab_db shell, .help for commands");
+    loop {
+        let line = match editor.readline("lab_db> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(readline_error(err)),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+
+        if line == ".quit" || line == ".exit" {
+            break;
+        }
+        // mistakes in a command do not end the session
+        if let Err(err) = execute(db, line) {
+            println!("Error: {}", err.report());
+        }
+    }
+
+    let _ = editor.save_history(&history);
+    Ok(())
+}
+
+fn execute(db: &DataBase, line: &str) -> Result<(), DataBaseError> {
+    let words = split_words(line);
+    // a line of empty quotes has no words
+    let Some(command) = words.first().map(String::as_str) else {
+        return Ok(());
+    };
+    let table = words.get(1).map(String::as_str).unwrap_or_default();
+    // `select * from ...` and `insert into ...` are SQL, commands are followed by a table name.
+    // `update <table> set ...` is SQL as well
+    let is_sql_update = words.get(2).is_some_and(|w| w.eq_ignore_ascii_case("set"));
+    let is_command =
+        command.starts_with('.') || (db.tables.iter().any(|t| t.name == table) && !is_sql_update);
+
+    match command {
+        ".help" => println!("{}", HELP),
+        ".tables" => {
+            for table in &db.tables {
+                println!("{}", table.name);
+            }
+        }
+        ".schema" => match db.tables.iter().find(|t| t.name == table) {
+            Some(table) => {
+                for (name, dtype) in &table.columns {
+                    println!("{} {}", name, dtype);
+                }
+            }
+            None => println!("Usage: .schema <table>"),
+        },
+        "insert" if is_command => {
+            db.insert_values(table, &assignments(&words[2..])?)?;
+            println!("Rows inserted: 1");
+        }
+        "update" if is_command && words.len() > 3 => {
+            let key = parse_value(&words[2]);
+            let changed = db.update(table, &key, &assignments(&words[3..])?)?;
+            println!("Rows updated: {}", changed as usize);
+        }
+        "delete" if is_command && words.len() == 3 => {
+            let changed = db.delete(table, &parse_value(&words[2]))?;
+            println!("Rows deleted: {}", changed as usize);
+        }
+        "select" if is_command => {
+            let mut q = qr::Select::new(table);
+            let rest = &words[2..];
+            let where_idx = rest.iter().position(|w| w.eq_ignore_ascii_case("where"));
+            let columns = &rest[..where_idx.unwrap_or(rest.len())];
+            if !columns.is_empty() {
+                q = q.columns(&columns.to_vec());
+            }
+            // condition is raw SQL, it is taken from the line as typed, with its quotes
+            if let Some(start) = line.to_ascii_lowercase().find(" where ") {
+                q = q.conditions(line[start + 7..].trim());
+            }
+
+            let headers = if columns.is_empty() {
+                column_names(db, table)
+            } else {
+                columns.to_vec()
+            };
+            print_table(&headers, &db.select(&q)?);
+        }
+        "search" if is_command && words.len() > 2 => {
+            let hits = db.search(table, &words[2..].join(" "), 20)?;
+            let headers = column_names(db, table);
+            let rows: Vec<Vec<DataType>> = hits.into_iter().map(|hit| hit.row).collect();
+            print_table(&headers, &rows);
+        }
+        _ if is_command => println!("Wrong command, .help shows the usage"),
+        _ => run_sql(db, line)?,
+    }
+
+    Ok(())
+}
+
+fn run_sql(db: &DataBase, sql: &str) -> Result<(), DataBaseError> {
+    let mut stmt = db.connection.prepare(sql)?;
+    if stmt.column_count() == 0 {
+        let changed = stmt.execute([])?;
+        println!("Rows changed: {}", changed);
+        return Ok(());
+    }
+
+    let headers: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query([])?;
+    let mut result: Vec<Vec<DataType>> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut row_data = Vec::new();
+        for col_idx in 0..headers.len() {
+            row_data.push(row.get::<usize, DataType>(col_idx)?);
+        }
+        result.push(row_data);
+    }
+    print_table(&headers, &result);
+
+    Ok(())
+}
+
+// labels of all columns of the table
+fn column_names(db: &DataBase, table: &str) -> Vec<String> {
+    match db.tables.iter().find(|t| t.name == table) {
+        Some(t) => t
+            .columns
+            .iter()
+            .map(|(name, _)| t.label(name).to_string())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// columns padded to the widest value
+fn print_table(headers: &[String], rows: &[Vec<DataType>]) {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|val| val.to_string()).collect())
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &cells {
+        for (idx, cell) in row.iter().enumerate() {
+            if idx < widths.len() {
+                widths[idx] = widths[idx].max(cell.chars().count());
+            }
+        }
+    }
+
+    let line = |values: &[String]| {
+        let padded: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(val, width)| format!("{:<width$}", val, width = width))
+            .collect();
+        println!("{}", padded.join(" | "));
+    };
+    line(headers);
+    let separator: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    println!("{}", separator.join("-+-"));
+    for row in &cells {
+        line(row);
+    }
+    println!(
+        "({} {})",
+        cells.len(),
+        if cells.len() == 1 { "row" } else { "rows" }
+    );
+}
+
+// `col=value` words of insert and update
+fn assignments(words: &[String]) -> Result<Vec<(String, DataType)>, DataBaseError> {
+    let mut values = Vec::new();
+    for word in words {
+        match word.split_once('=') {
+            Some((col, val)) if !col.is_empty() => values.push((col.to_string(), parse_value(val))),
+            _ => {
+                return Err(DataBaseError::ConfigError(format!(
+                    "Expected column=value, got \"{}\"",
+                    word
+                )))
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+fn parse_value(word: &str) -> DataType {
+    if word.eq_ignore_ascii_case("null") {
+        DataType::Null
+    } else if let Ok(i) = word.parse::<i64>() {
+        DataType::Int(i)
+    } else if let Ok(f) = word.parse::<f64>() {
+        DataType::Float(f)
+    } else {
+        DataType::Text(word.to_string())
+    }
+}
+
+// splits on spaces, text in single quotes is kept together without the quotes
+fn split_words(line: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // doubled quote inside quotes is the quote itself, as in SQL
+            '\'' if quoted && chars.peek() == Some(&'\'') => {
+                word.push('\'');
+                chars.next();
+            }
+            '\'' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+fn history_path() -> PathBuf {
+    match env::var("HOME") {
+        Ok(home) => PathBuf::from(home).join(HISTORY_FILE),
+        Err(_) => PathBuf::from(HISTORY_FILE),
+    }
+}
+
+fn readline_error(err: ReadlineError) -> DataBaseError {
+    match err {
+        ReadlineError::Io(err) => DataBaseError::IoError(err),
+        err => DataBaseError::IoError(io::Error::other(err.to_string())),
+    }
+}
+
+// completes commands, table names and column names of tables
+struct NameCompleter {
+    tables: Vec<(String, Vec<String>)>,
+}
+
+impl NameCompleter {
+    fn new(tables: &[Table]) -> Self {
+        NameCompleter {
+            tables: tables
+                .iter()
+                .map(|t| {
+                    let columns = t.columns.iter().map(|(name, _)| name.clone()).collect();
+                    (t.name.clone(), columns)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Completer for NameCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before
+            .rfind(|c: char| c.is_whitespace() || "=(),.\"".contains(c))
+            .map_or(0, |idx| idx + 1);
+        let prefix = &before[start..];
+
+        let mut names: Vec<&str> = Vec::new();
+        if before[..start].trim().is_empty() {
+            names.extend(COMMANDS);
+        }
+        for (table, _) in &self.tables {
+            names.push(table);
+        }
+        // columns of the tables mentioned in the line, of all tables if there are none
+        let mentioned: Vec<&(String, Vec<String>)> = self
+            .tables
+            .iter()
+            .filter(|(table, _)| line.contains(table.as_str()))
+            .collect();
+        for (_, columns) in if mentioned.is_empty() {
+            self.tables.iter().collect()
+        } else {
+            mentioned
+        } {
+            names.extend(columns.iter().map(String::as_str));
+        }
+
+        names.sort();
+        names.dedup();
+        let candidates = names
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair {
+                display: name.to_string(),
+                replacement: name.to_string(),
+            })
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for NameCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for NameCompleter {}
+
+impl Validator for NameCompleter {}
+
+impl Helper for NameCompleter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_quoted_words() {
+        assert_eq!(
+            split_words("insert T name='a b' note='it''s'"),
+            vec!["insert", "T", "name=a b", "note=it's"]
+        );
+        assert!(split_words("''").is_empty());
+        assert!(split_words("  '' ''  ").is_empty());
+    }
+
+    #[test]
+    fn execute_empty_words() {
+        let db = DataBase::from_file(":memory:").unwrap();
+        assert!(execute(&db, "''").is_ok());
+    }
+}
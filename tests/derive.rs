@@ -0,0 +1,56 @@
+use databaser::database::definitions::DataBase;
+use databaser::query_builder as qr;
+use databaser::DbRecord;
+
+#[derive(DbRecord, Debug, PartialEq)]
+#[db(table = "Samples")]
+struct Sample {
+    #[db(primary_key)]
+    id: i64,
+    #[db(rename = "label")]
+    name: String,
+    weight: f64,
+    checked: bool,
+    note: Option<String>,
+    raw: Vec<u8>,
+}
+
+#[test]
+fn record_round_trip() {
+    let table = Sample::table();
+    assert_eq!(table.name, "Samples");
+    assert_eq!(table.primary_key, "id");
+    assert_eq!(table.columns[0].1, "INTEGER PRIMARY KEY");
+    assert_eq!(table.columns[1].0, "label");
+
+    let mut db = DataBase::from_file(":memory:").unwrap();
+    db.create_table(&table).unwrap();
+    db.tables.push(table);
+
+    let samples = vec![
+        Sample {
+            id: 1,
+            name: "soil".to_string(),
+            weight: 12.5,
+            checked: true,
+            note: None,
+            raw: vec![1, 2, 3],
+        },
+        Sample {
+            id: 2,
+            name: "water".to_string(),
+            weight: 0.25,
+            checked: false,
+            note: Some("cloudy".to_string()),
+            raw: Vec::new(),
+        },
+    ];
+    for sample in &samples {
+        db.insert_record(sample).unwrap();
+    }
+
+    let read: Vec<Sample> = db
+        .select_records(&qr::Select::new("Samples").order_by("id", qr::Order::Asc))
+        .unwrap();
+    assert_eq!(read, samples);
+}